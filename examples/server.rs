@@ -9,5 +9,5 @@ use ice::stun;
 
 fn main() {
     let host = "127.0.0.1:3478";
-    stun::server::run(host, "udp");
+    stun::server::run(host, None, "udp");
 }
\ No newline at end of file
@@ -1,9 +1,17 @@
-#![feature(lookup_host)]
 #![allow(dead_code, unused_imports, unused_variables, unused_mut)]
 #![allow(unused_must_use, unreachable_code, non_snake_case, unused_assignments)]
 
 extern crate url;
 extern crate rand;
+extern crate hmac;
+extern crate sha1;
+extern crate sha2;
+extern crate md5;
+extern crate crc32fast;
+extern crate stringprep;
+extern crate unicode_normalization;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 // use std::string::ToString;
 // use std::convert::AsRef;
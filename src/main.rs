@@ -1,9 +1,17 @@
-#![feature(lookup_host)]
 #![allow(dead_code, unused_imports, unused_variables, unused_mut)]
 #![allow(unused_must_use, unreachable_code, non_snake_case, unused_assignments)]
 
 extern crate url;
 extern crate rand;
+extern crate hmac;
+extern crate sha1;
+extern crate sha2;
+extern crate md5;
+extern crate crc32fast;
+extern crate stringprep;
+extern crate unicode_normalization;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 use std::string::ToString;
 use std::convert::AsRef;
@@ -22,7 +30,7 @@ fn main() {
     122, 253, 177, 191, 174, 164, 118, 181, 61];
 
     let mut client = stun::client::Client::new(None).unwrap();
-    client.set_server_uri(stun_server);
+    client.set_server_uri(stun_server).unwrap();
 
     let res = client.send(&data);
 
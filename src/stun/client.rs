@@ -2,55 +2,632 @@
 use std::str::FromStr;
 use std::string::ToString;
 
-use std::net::{ SocketAddr, IpAddr, TcpStream, UdpSocket };
-use super::{url_parse, STUN_PORT, STUNS_PORT};
+use std::io;
+use std::thread;
+use std::cmp;
+use std::time::{Duration, Instant};
+use std::net::{ SocketAddr, IpAddr, Ipv4Addr, TcpStream, UdpSocket };
+use super::{url_parse, url_parse_with_default_port, Error, STUN_PORT, STUNS_PORT};
+use super::constant::{STUN_RTO, STUN_RC, STUN_RM};
+use super::packet::{Packet, PacketRef, MessageBuilder, Header, Attribute, AttributeType, Class, Method, TransactionId, HEADER_SIZE};
 
 // https://zh.wikipedia.org/wiki/%E7%BD%91%E7%BB%9C%E5%9C%B0%E5%9D%80%E8%BD%AC%E6%8D%A2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Nat {
+    OpenInternet,          // 公网地址，没有 NAT
     FullCone,              // 一对一
     AddressRestrictedCone, // 地址受限锥形 NAT
     PortRestrictedCone,    // 端口受限锥形 NAT
-    Symmetric              // 对称 NAT
+    Symmetric,             // 对称 NAT
+    SymmetricFirewall,     // 公网地址，但防火墙只放行回应
+    UdpBlocked             // UDP 不通
+}
+
+/// A RFC 3489 test: a Binding request sent to `server`, asking it to answer
+/// from its other IP address and / or port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatTest {
+    pub server     : SocketAddr,
+    pub change_ip  : bool,
+    pub change_port: bool
+}
+
+/// The addresses a `NatTest` response carries: (XOR-)MAPPED-ADDRESS, the
+/// server's other address (CHANGED-ADDRESS or OTHER-ADDRESS) and the address
+/// the response was sent from (RESPONSE-ORIGIN).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatResponse {
+    pub mapped_address : SocketAddr,
+    pub changed_address: Option<SocketAddr>,
+    pub response_origin: Option<SocketAddr>
+}
+
+/// Mapping and filtering behaviours of a NAT (RFC 4787).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behavior {
+    EndpointIndependent,
+    AddressDependent,
+    AddressAndPortDependent
+}
+
+/// What `Client::behavior` found out, `mapped_address` is the client's
+/// address as seen by the server (its local address when there is no NAT).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatBehavior {
+    pub mapped_address: SocketAddr,
+    pub mapping       : Behavior,
+    pub filtering     : Behavior,
+    pub hairpinning   : bool
+}
+
+/**
+The RFC 3489 NAT type discovery, `test` runs a test and returns its
+response, `None` when there was none. `local_socket_addr` is the address
+the tests are sent from.
+                     +--------+
+                     |  Test  |
+                     |   I    |
+                     +--------+
+                          |
+                          |
+                          V
+                         /\              /\
+                      N /  \ Y          /  \ Y             +--------+
+       UDP     <-------/Resp\--------->/ IP \------------->|  Test  |
+       Blocked         \ ?  /          \Same/              |   II   |
+                        \  /            \? /               +--------+
+                         \/              \/                    |
+                                          | N                  |
+                                          |                    V
+                                          V                    /\
+                                      +--------+  Sym.      N /  \
+                                      |  Test  |  UDP    <---/Resp\
+                                      |   II   |  Firewall   \ ?  /
+                                      +--------+              \  /
+                                          |                    \/
+                                          V                     |Y
+               /\                         /\                     |
+Symmetric  N  /  \       +--------+   N  /  \                    V
+   NAT  <--- / IP \<-----|  Test  |<--- /Resp\               Open
+             \Same/      |   I    |     \ ?  /               Internet
+              \? /       +--------+      \  /
+               \/                         \/
+               |                           |Y
+               |                           |
+               |                           V
+               |Y                        Full
+               |                         Cone
+               V              /\
+           +--------+        /  \ Y
+           |  Test  |------>/Resp\---->Restricted
+           |   III  |       \ ?  /
+           +--------+        \  /
+                              \/
+                               |N
+                               |       Port
+                               +------>Restricted
+
+Figure 2: Flow for type discovery process
+
+Test I is sent to `server` and then to its CHANGED-ADDRESS, Test II asks
+for a response from the other IP address and port, Test III from the
+other port only.
+
+Refs:
+    https://tools.ietf.org/html/rfc3489#section-10.1
+**/
+pub fn detect_nat<F>(server: SocketAddr, local_socket_addr: SocketAddr, mut test: F) -> Result<Nat, Error>
+    where F: FnMut(&NatTest) -> Result<Option<NatResponse>, Error> {
+    let test_1 = match test(&NatTest { server, change_ip: false, change_port: false })? {
+        Some(response) => response,
+        None => return Ok(Nat::UdpBlocked)
+    };
+    let test_2 = test(&NatTest { server, change_ip: true, change_port: true })?;
+    if test_1.mapped_address == local_socket_addr {
+        return Ok(if test_2.is_some() { Nat::OpenInternet } else { Nat::SymmetricFirewall });
+    }
+    if test_2.is_some() {
+        return Ok(Nat::FullCone);
+    }
+    let changed_address = test_1.changed_address
+        .ok_or_else(|| Error::MissingAttribute(AttributeType::ChangedAddress.to_u32()))?;
+    let test_1_changed = match test(&NatTest { server: changed_address, change_ip: false, change_port: false })? {
+        Some(response) => response,
+        None => return Err(Error::Timeout)
+    };
+    if test_1_changed.mapped_address != test_1.mapped_address {
+        return Ok(Nat::Symmetric);
+    }
+    match test(&NatTest { server, change_ip: false, change_port: true })? {
+        Some(_) => Ok(Nat::AddressRestrictedCone),
+        None    => Ok(Nat::PortRestrictedCone)
+    }
+}
+
+/**
+How long to wait for a response after each transmission of a request:
+`rto` after the first one, twice as long after every retransmission, and
+`rm` times `rto` after the last of the `rc` transmissions. With the default
+values (500 ms, 7, 16) requests are sent at 0 ms, 500 ms, 1500 ms, 3500 ms,
+7500 ms, 15500 ms and 31500 ms, and the client gives up at 39500 ms.
+The timers saturate at `Duration::MAX`, `rc` must be at least 1.
+
+Refs:
+    https://tools.ietf.org/html/rfc5389#section-7.2.1
+**/
+pub fn retransmission_timeouts(rto: Duration, rc: u32, rm: u32) -> Result<Vec<Duration>, Error> {
+    if rc == 0 {
+        return Err(Error::InvalidRetransmissionCount(rc));
+    }
+    Ok((0..rc).map(|n| if n + 1 == rc {
+        rto.saturating_mul(rm)
+    } else {
+        rto.saturating_mul(2u32.saturating_pow(n))
+    }).collect())
+}
+
+/**
+The RFC 5780 mapping behaviour test, `first` is the response to a plain
+Binding request to `server` (test I) and `test` runs the next tests.
+
+Test II is sent to the alternate IP address and the primary port, the
+mapping is endpoint independent when it gets the same mapped address as
+test I. Test III is sent to the alternate IP address and port, the mapping
+is address dependent when it gets the same mapped address as test II,
+address and port dependent otherwise.
+
+Refs:
+    https://tools.ietf.org/html/rfc5780#section-4.3
+**/
+pub fn mapping_behavior<F>(server: SocketAddr, local_socket_addr: SocketAddr, first: &NatResponse, mut test: F) -> Result<Behavior, Error>
+    where F: FnMut(&NatTest) -> Result<Option<NatResponse>, Error> {
+    if first.mapped_address == local_socket_addr {
+        return Ok(Behavior::EndpointIndependent);
+    }
+    let other = first.changed_address
+        .ok_or_else(|| Error::MissingAttribute(AttributeType::OtherAddress.to_u32()))?;
+    let test_2 = test(&NatTest { server: SocketAddr::new(other.ip(), server.port()), change_ip: false, change_port: false })?
+        .ok_or(Error::Timeout)?;
+    if test_2.mapped_address == first.mapped_address {
+        return Ok(Behavior::EndpointIndependent);
+    }
+    let test_3 = test(&NatTest { server: other, change_ip: false, change_port: false })?
+        .ok_or(Error::Timeout)?;
+    match test_3.mapped_address == test_2.mapped_address {
+        true  => Ok(Behavior::AddressDependent),
+        false => Ok(Behavior::AddressAndPortDependent)
+    }
+}
+
+/**
+The RFC 5780 filtering behaviour test, `first` is the response to a plain
+Binding request to `server` (test I) and `test` runs the next tests.
+
+Test II asks the server to answer from its alternate IP address and port,
+the filtering is endpoint independent when the response gets through. Test
+III asks it to answer from its alternate port only, the filtering is address
+dependent when that response gets through, address and port dependent
+otherwise. A response whose RESPONSE-ORIGIN is not the address asked for
+fails with `Error::UnexpectedResponseOrigin`, the server ignored CHANGE-REQUEST.
+
+Refs:
+    https://tools.ietf.org/html/rfc5780#section-4.4
+**/
+pub fn filtering_behavior<F>(server: SocketAddr, first: &NatResponse, mut test: F) -> Result<Behavior, Error>
+    where F: FnMut(&NatTest) -> Result<Option<NatResponse>, Error> {
+    let other = first.changed_address
+        .ok_or_else(|| Error::MissingAttribute(AttributeType::OtherAddress.to_u32()))?;
+    if let Some(response) = test(&NatTest { server, change_ip: true, change_port: true })? {
+        check_response_origin(&response, other)?;
+        return Ok(Behavior::EndpointIndependent);
+    }
+    match test(&NatTest { server, change_ip: false, change_port: true })? {
+        Some(response) => {
+            check_response_origin(&response, SocketAddr::new(server.ip(), other.port()))?;
+            Ok(Behavior::AddressDependent)
+        },
+        None => Ok(Behavior::AddressAndPortDependent)
+    }
+}
+
+fn check_response_origin(response: &NatResponse, expected: SocketAddr) -> Result<(), Error> {
+    match response.response_origin {
+        Some(origin) if origin != expected => Err(Error::UnexpectedResponseOrigin(origin)),
+        _ => Ok(())
+    }
+}
+
+/// The time source of `binding_lifetime`, tests replace it with a simulated
+/// one so that they don't have to wait. `sleep` may return early or late,
+/// the waits end when `now` has reached their deadline.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// What `binding_lifetime` found out: a binding is still there after
+/// `alive`, and gone after `expired` (`None` when it outlived the longest
+/// wait tried). The keepalive interval should be shorter than `alive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindingLifetime {
+    pub alive  : Duration,
+    pub expired: Option<Duration>
+}
+
+/**
+Measure how long the NAT keeps an idle UDP binding (RFC 5780 4.6): every
+trial opens a new binding with `open`, lets it idle for a while and checks
+with `is_alive` whether it still exists.
+
+The idle time starts at `resolution` and is doubled, up to `max`, until a
+binding has expired, the lifetime is then searched by bisection until it is
+known within `resolution`. Fails with `Error::DurationTooLong` when `max`
+is too long to wait for on `clock`.
+
+Refs:
+    https://tools.ietf.org/html/rfc5780#section-4.6
+**/
+pub fn binding_lifetime<C, B, O, A>(clock: &C, max: Duration, resolution: Duration, mut open: O, mut is_alive: A) -> Result<BindingLifetime, Error>
+    where C: Clock, O: FnMut() -> Result<B, Error>, A: FnMut(&B) -> Result<bool, Error> {
+    let mut trial = |idle: Duration| -> Result<bool, Error> {
+        let binding = open()?;
+        // The binding idles from the moment it is opened, until `idle` has
+        // elapsed on `clock` however long its sleeps actually are.
+        let deadline = clock.now().checked_add(idle).ok_or(Error::DurationTooLong(idle))?;
+        let mut now = clock.now();
+        while now < deadline {
+            clock.sleep(deadline - now);
+            now = clock.now();
+        }
+        is_alive(&binding)
+    };
+
+    if clock.now().checked_add(max).is_none() {
+        return Err(Error::DurationTooLong(max));
+    }
+    let resolution = cmp::max(resolution, Duration::from_millis(1));
+    let mut alive  = Duration::from_secs(0);
+    let mut idle   = cmp::min(resolution, max);
+    let mut expired = loop {
+        if !trial(idle)? {
+            break idle;
+        }
+        alive = idle;
+        if idle >= max {
+            return Ok(BindingLifetime { alive, expired: None });
+        }
+        idle = idle.checked_mul(2).map_or(max, |double| cmp::min(double, max));
+    };
+    while expired - alive > resolution {
+        let idle = alive + (expired - alive) / 2;
+        match trial(idle)? {
+            true  => alive = idle,
+            false => expired = idle
+        }
+    }
+    Ok(BindingLifetime { alive, expired: Some(expired) })
 }
 
 #[derive(Debug)]
 pub struct Client {
     server: Option<SocketAddr>,
-    client: UdpSocket
+    client: UdpSocket,
+    rto   : Duration,
+    rc    : u32,
+    rm    : u32
 }
 
 impl Client {
-    pub fn new(uri: Option<&str>) -> Result<Self, &'static str> {
-        let mut url: String = String::new();
-        if uri.is_none() {
-            url = format!("stun://127.0.0.1:{}", STUN_PORT);
-        } else {
-            url = uri.unwrap().to_owned();
+    /// A client bound to the local address `uri`, any address of the host
+    /// (`0.0.0.0:0`) when `None`. A bare IP address (`"0.0.0.0"`, `"::"`) or
+    /// any other form parsed by `url_parse` (`"localhost"`, `"[::1]:5000"`,
+    /// `"stun:0.0.0.0"`) gets an ephemeral port unless it has one.
+    pub fn new(uri: Option<&str>) -> Result<Self, Error> {
+        let socket_addr = match uri {
+            None      => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            Some(uri) => match uri.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, 0),
+                Err(_) => url_parse_with_default_port(uri, 0)?
+            }
         };
+        Ok(Client {
+            server: None,
+            client: UdpSocket::bind(socket_addr)?,
+            rto   : Duration::from_millis(STUN_RTO),
+            rc    : STUN_RC,
+            rm    : STUN_RM
+        })
+    }
+    pub fn set_server_uri(&mut self, uri: &str) -> Result<(), Error> {
+        self.server = Some(url_parse(uri)?);
+        Ok(())
+    }
+    /// The retransmission timers of `binding`, see `retransmission_timeouts`.
+    pub fn set_retransmission(&mut self, rto: Duration, rc: u32, rm: u32) -> Result<(), Error> {
+        if rc == 0 {
+            return Err(Error::InvalidRetransmissionCount(rc));
+        }
+        self.rto = rto;
+        self.rc  = rc;
+        self.rm  = rm;
+        Ok(())
+    }
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.client.local_addr()?)
+    }
+    pub fn send(&self, msg: &[u8]) -> Result<usize, Error> {
+        match self.server {
+            Some(target) => Ok(self.client.send_to(msg, target)?),
+            None         => Err(Error::ServerNotSet)
+        }
+    }
+    /// Receive a message from the server, an error response is returned as
+    /// `Error::ErrorResponse` carrying the server's reason phrase.
+    pub fn recv(&self, buffer: &mut [u8]) -> Result<Packet, Error> {
+        let (size, _) = self.client.recv_from(buffer)?;
+        let packet = Packet::from_bytes(&buffer[..size])?;
+        if packet.header().class() == &Class::FailureResponse {
+            if let Some((code, reason)) = packet.error_code() {
+                return Err(Error::ErrorResponse { code: code.clone(), reason: reason.to_owned() });
+            }
+        }
+        Ok(packet)
+    }
+    /// Send a Binding request with a new transaction ID to the server and
+    /// return the reflexive transport address from its response, taken from
+    /// XOR-MAPPED-ADDRESS (or MAPPED-ADDRESS, for RFC 3489 servers).
+    ///
+    /// The request is retransmitted following the RTO schedule (see
+    /// `retransmission_timeouts`) until a response with the same transaction
+    /// ID arrives, other datagrams are ignored. Fails with `Error::Timeout`
+    /// when there is no response, with `Error::ErrorResponse` on an error
+    /// response.
+    pub fn binding(&self) -> Result<SocketAddr, Error> {
+        let server = self.server.ok_or(Error::ServerNotSet)?;
+        let header = Header::new(Class::Request, Method::Binding, TransactionId::random()?);
+        let mut request = [0u8; HEADER_SIZE];
+        let size = MessageBuilder::with_header(&mut request, header.clone())?.finish()?;
+
+        let result = self.transaction(server, &request[..size], &header);
+        self.client.set_read_timeout(None)?;
+        mapped_address(&result?)
+    }
+    /// Find out the type of NAT between the client and the Internet, the
+    /// server must support CHANGE-REQUEST (RFC 3489 or RFC 5780), see `detect_nat`.
+    pub fn nat(&self) -> Result<Nat, Error> {
+        let server = self.server.ok_or(Error::ServerNotSet)?;
+        detect_nat(server, self.source_addr(server)?, |test| self.nat_test(test))
+    }
+    /// Find out the mapping and filtering behaviours of the NAT between the
+    /// client and the server and whether it supports hairpinning, the server
+    /// must support RFC 5780 (have an alternate address). See
+    /// `mapping_behavior`, `filtering_behavior` and `hairpinning`.
+    pub fn behavior(&self) -> Result<NatBehavior, Error> {
+        let server = self.server.ok_or(Error::ServerNotSet)?;
+        let first  = self.behavior_test(&NatTest { server, change_ip: false, change_port: false })?
+            .ok_or(Error::Timeout)?;
+        let mapping   = mapping_behavior(server, self.source_addr(server)?, &first, |test| self.behavior_test(test))?;
+        let filtering = filtering_behavior(server, &first, |test| self.behavior_test(test))?;
+        Ok(NatBehavior {
+            mapped_address: first.mapped_address,
+            mapping,
+            filtering,
+            hairpinning   : self.hairpinning(first.mapped_address)?
+        })
+    }
+    /// Whether the NAT forwards a datagram sent to the client's own mapped
+    /// address back to the client: a Binding request is sent to `mapped_address`
+    /// from another local socket (RFC 5780 4.5). Nothing answers the request,
+    /// so it is only sent twice, `rto` apart, and hairpinning is reported as
+    /// unsupported when it has not come back `rto` after the second one.
+    pub fn hairpinning(&self, mapped_address: SocketAddr) -> Result<bool, Error> {
+        let socket = UdpSocket::bind(SocketAddr::new(self.local_addr()?.ip(), 0))?;
+        let header = Header::new(Class::Request, Method::Binding, TransactionId::random()?);
+        let mut request = [0u8; HEADER_SIZE];
+        let size = MessageBuilder::with_header(&mut request, header.clone())?.finish()?;
 
-        let socket_addr   = url_parse(&url).expect("local uri format error.");
-        let client_socket = UdpSocket::bind(socket_addr).expect("Couldn't bind port");
+        let transaction = Transaction { request: &request[..size], header: &header, class: Class::Request };
+        let result = self.exchange_within(&socket, &self.client, mapped_address, &transaction, &[self.rto, self.rto]);
+        self.client.set_read_timeout(None)?;
+        match result {
+            Ok(_)               => Ok(true),
+            Err(Error::Timeout) => Ok(false),
+            Err(e)              => Err(e)
+        }
+    }
+    /// Measure the lifetime of the NAT bindings, see `binding_lifetime`.
+    ///
+    /// A binding is opened by a Binding request from a new local socket, it
+    /// is checked by a Binding request from the client socket carrying
+    /// RESPONSE-PORT, asking the server to answer to the binding's port, the
+    /// binding is alive when the new socket receives the response. As an
+    /// expired binding never gets one, the check is only sent twice, `rto`
+    /// apart, like `hairpinning`'s request. The server must support
+    /// RESPONSE-PORT (RFC 5780).
+    pub fn binding_lifetime<C: Clock>(&self, clock: &C, max: Duration, resolution: Duration) -> Result<BindingLifetime, Error> {
+        let server = self.server.ok_or(Error::ServerNotSet)?;
+        let local_ip = self.local_addr()?.ip();
+        let result = binding_lifetime(clock, max, resolution, || {
+            let socket = UdpSocket::bind(SocketAddr::new(local_ip, 0))?;
+            let header = Header::new(Class::Request, Method::Binding, TransactionId::random()?);
+            let mut request = [0u8; HEADER_SIZE];
+            let size = MessageBuilder::with_header(&mut request, header.clone())?.finish()?;
+            let transaction = Transaction { request: &request[..size], header: &header, class: Class::SuccessResponse };
+            let response = self.exchange(&socket, &socket, server, &transaction)?;
+            Ok((socket, mapped_address(&response)?))
+        }, |&(ref socket, binding)| {
+            let header = Header::new(Class::Request, Method::Binding, TransactionId::random()?);
+            let mut request = [0u8; 64];
+            let size = MessageBuilder::with_header(&mut request, header.clone())?
+                .attribute(&Attribute::ResponsePort(binding.port()))?
+                .finish()?;
+            let transaction = Transaction { request: &request[..size], header: &header, class: Class::SuccessResponse };
+            match self.exchange_within(&self.client, socket, server, &transaction, &[self.rto, self.rto]) {
+                Ok(response)        => mapped_address(&response).map(|_| true),
+                Err(Error::Timeout) => Ok(false),
+                Err(e)              => Err(e)
+            }
+        });
+        self.client.set_read_timeout(None)?;
+        result
+    }
+    /// The address the requests to `server` are sent from: when the client is
+    /// bound to any address (`0.0.0.0` or `::`), the one of the interface the
+    /// system routes them through.
+    fn source_addr(&self, server: SocketAddr) -> Result<SocketAddr, Error> {
+        let local_addr = self.local_addr()?;
+        if !local_addr.ip().is_unspecified() {
+            return Ok(local_addr);
+        }
+        let socket = UdpSocket::bind(SocketAddr::new(local_addr.ip(), 0))?;
+        socket.connect(server)?;
+        Ok(SocketAddr::new(socket.local_addr()?.ip(), local_addr.port()))
+    }
+    /// Send a RFC 3489 Binding request carrying CHANGE-REQUEST.
+    fn nat_test(&self, test: &NatTest) -> Result<Option<NatResponse>, Error> {
+        let header = Header::new_classic(Class::Request, Method::Binding, TransactionId::random_classic()?);
+        self.test(test, header)
+    }
+    /// Send a RFC 5389 Binding request, carrying CHANGE-REQUEST when the test
+    /// asks for a change.
+    fn behavior_test(&self, test: &NatTest) -> Result<Option<NatResponse>, Error> {
+        let header = Header::new(Class::Request, Method::Binding, TransactionId::random()?);
+        self.test(test, header)
+    }
+    fn test(&self, test: &NatTest, header: Header) -> Result<Option<NatResponse>, Error> {
+        let mut request = [0u8; 64];
+        let mut builder = MessageBuilder::with_header(&mut request, header.clone())?;
+        if header.is_classic() || test.change_ip || test.change_port {
+            builder = builder.attribute(&Attribute::ChangeRequest { change_ip: test.change_ip, change_port: test.change_port })?;
+        }
+        let size = builder.finish()?;
 
-        match socket_addr.ip().is_loopback() {
-            true => Ok(Client { server: None, client: client_socket}),
-            _    => Err("local_uri ip error.")
+        let result = self.transaction(test.server, &request[..size], &header);
+        self.client.set_read_timeout(None)?;
+        let response = match result {
+            Ok(response)        => response,
+            Err(Error::Timeout) => return Ok(None),
+            Err(e)              => return Err(e)
+        };
+        if let Some((code, reason)) = response.error_code() {
+            return Err(Error::ErrorResponse { code: code.clone(), reason: reason.to_owned() });
+        }
+        let mut mapped_address  = None;
+        let mut changed_address = None;
+        let mut response_origin = None;
+        for attribute in response.attributes() {
+            match *attribute {
+                Attribute::XorMappedAddress(socket_addr) => mapped_address = Some(socket_addr),
+                Attribute::MappedAddress(socket_addr) if mapped_address.is_none() => mapped_address = Some(socket_addr),
+                Attribute::ChangedAddress(socket_addr) | Attribute::OtherAddress(socket_addr) => changed_address = Some(socket_addr),
+                Attribute::ResponseOrigin(socket_addr) => response_origin = Some(socket_addr),
+                _ => { }
+            }
+        }
+        match mapped_address {
+            Some(mapped_address) => Ok(Some(NatResponse {
+                mapped_address,
+                changed_address,
+                response_origin
+            })),
+            None => Err(Error::MissingAttribute(AttributeType::MappedAddress.to_u32()))
         }
     }
-    pub fn set_server_uri(&mut self, uri: &str) -> bool {
-        let stun_server_socket_addr = url_parse(uri).expect("server uri format error.");
-        self.server = Some(stun_server_socket_addr);
-        true
+    /// Send `request` until a response to it is received.
+    fn transaction(&self, server: SocketAddr, request: &[u8], header: &Header) -> Result<Packet, Error> {
+        let transaction = Transaction { request, header, class: Class::SuccessResponse };
+        self.exchange(&self.client, &self.client, server, &transaction)
     }
-    pub fn send(&self, msg: &[u8]) -> Result<usize, &'static str> {
-        assert_eq!(self.server.is_some(), true);
-        let target = self.server.unwrap();
-        match self.client.send_to(msg, target) {
-            Ok(size) => Ok(size),
-            Err(_)   => Err("send error.")
+    /// Send the transaction's request from `sender` to `destination` until
+    /// `receiver` receives its response, see `Transaction`.
+    fn exchange(&self, sender: &UdpSocket, receiver: &UdpSocket, destination: SocketAddr,
+        transaction: &Transaction) -> Result<Packet, Error> {
+        let timeouts = retransmission_timeouts(self.rto, self.rc, self.rm)?;
+        self.exchange_within(sender, receiver, destination, transaction, &timeouts)
+    }
+    /// `exchange` with the given retransmission timers.
+    fn exchange_within(&self, sender: &UdpSocket, receiver: &UdpSocket, destination: SocketAddr,
+        transaction: &Transaction, timeouts: &[Duration]) -> Result<Packet, Error> {
+        let header = transaction.header;
+        let mut buffer = [0u8; 2048];
+        for &timeout in timeouts {
+            sender.send_to(transaction.request, destination)?;
+            let deadline = Instant::now().checked_add(timeout);
+            while let Some(size) = recv_before(receiver, &mut buffer, deadline)? {
+                let is_response = match PacketRef::new(&buffer[..size]) {
+                    Ok(packet) => packet.magic_cookie() == header.magic_cookie()
+                        && &packet.transaction_id() == header.transaction_id(),
+                    Err(_)     => false
+                };
+                if !is_response {
+                    continue;
+                }
+                if let Ok(packet) = Packet::from_bytes(&buffer[..size]) {
+                    let received = packet.header().class();
+                    if received == &transaction.class
+                        || (transaction.class == Class::SuccessResponse && received == &Class::FailureResponse) {
+                        return Ok(packet);
+                    }
+                }
+            }
         }
+        Err(Error::Timeout)
     }
-    pub fn nat (&self) {
-        assert_eq!(self.server.is_some(), true);
+}
+
+/// A request and the class of the message that ends its transaction: a
+/// message of the same transaction ID and of class `class` (a failure
+/// response is accepted along with a success response).
+struct Transaction<'a> {
+    request: &'a [u8],
+    header : &'a Header,
+    class  : Class
+}
 
+/// The reflexive transport address in a Binding response, an error response
+/// is turned into `Error::ErrorResponse`.
+fn mapped_address(response: &Packet) -> Result<SocketAddr, Error> {
+    if response.header().class() == &Class::FailureResponse {
+        return match response.error_code() {
+            Some((code, reason)) => Err(Error::ErrorResponse { code: code.clone(), reason: reason.to_owned() }),
+            None => Err(Error::MissingAttribute(AttributeType::ErrorCode.to_u32()))
+        };
+    }
+    let mut mapped_address = None;
+    for attribute in response.attributes() {
+        match *attribute {
+            Attribute::XorMappedAddress(socket_addr) => return Ok(socket_addr),
+            Attribute::MappedAddress(socket_addr)    => mapped_address = Some(socket_addr),
+            _ => { }
+        }
+    }
+    mapped_address.ok_or_else(|| Error::MissingAttribute(AttributeType::XorMappedAddress.to_u32()))
+}
+
+/// Receive a datagram on `socket`, `None` once `deadline` has passed
+/// (a `None` deadline, too far away to be represented, never passes).
+fn recv_before(socket: &UdpSocket, buffer: &mut [u8], deadline: Option<Instant>) -> Result<Option<usize>, Error> {
+    let now = Instant::now();
+    match deadline {
+        Some(deadline) if now >= deadline => return Ok(None),
+        Some(deadline) => socket.set_read_timeout(Some(deadline - now))?,
+        None           => socket.set_read_timeout(None)?
+    }
+    match socket.recv_from(buffer) {
+        Ok((size, _)) => Ok(Some(size)),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(None),
+        Err(e) => Err(Error::from(e))
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 
 
-pub const PUBLIC_STUN_SERVERS: [&'static str; 11] = [
+pub const PUBLIC_STUN_SERVERS: [&str; 11] = [
     "stun:stun.xten.net:3478",
     "stun:sip.iptel.org:3478",
     "stun:tesla.divmod.net:3478",
@@ -22,6 +22,14 @@ pub const STUNS_PORT: u16 = 5349;
 
 pub const STUN_MAGIC_COOKIE: u32 = 0x2112A442;
 
+// https://tools.ietf.org/html/rfc5389#section-7.2.1
+// initial retransmission timeout (in milliseconds), doubled after each retransmission
+pub const STUN_RTO: u64 = 500;
+// number of requests sent before giving up
+pub const STUN_RC: u32  = 7;
+// after the last request, wait STUN_RM times the initial RTO for a response
+pub const STUN_RM: u32  = 16;
+
 pub const STUN_FINGERPRINT_XOR_VALUE: u32 = 0x5354554E; // STUN FINGERPRINT XOR Value
 
 // default allocation lifetime (in seconds) unless refreshed 
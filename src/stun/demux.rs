@@ -0,0 +1,40 @@
+
+/**
+Demultiplexing of the protocols sharing one port, by the first byte of
+the datagram (RFC 7983 7):
+           +----------------+
+           |        [0..3] -+--> forward to STUN
+           |                |
+           |      [16..19] -+--> forward to ZRTP
+           |                |
+packet --> |      [20..63] -+--> forward to DTLS
+           |                |
+           |      [64..79] -+--> forward to TURN Channel
+           |                |
+           |    [128..191] -+--> forward to RTP/RTCP
+           +----------------+
+
+Refs:
+    https://tools.ietf.org/html/rfc7983#section-7
+**/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Stun,
+    Zrtp,
+    Dtls,
+    ChannelData,
+    Rtp,
+    /// The packet matches none of the ranges and should be dropped.
+    Unknown
+}
+
+pub fn classify(bytes: &[u8]) -> Protocol {
+    match bytes.first() {
+        Some(&(0 ..= 3))     => Protocol::Stun,
+        Some(&(16 ..= 19))   => Protocol::Zrtp,
+        Some(&(20 ..= 63))   => Protocol::Dtls,
+        Some(&(64 ..= 79))   => Protocol::ChannelData,
+        Some(&(128 ..= 191)) => Protocol::Rtp,
+        _                    => Protocol::Unknown
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::io;
+use std::error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use super::packet::ErrorCode;
+
+/// Errors of the STUN codec, client and server.
+#[derive(Debug)]
+pub enum Error {
+    /// The input ended at `offset` while `needed` more bytes were expected.
+    Truncated { offset: usize, needed: usize },
+    /// The header length does not match the size of the message body.
+    LengthMismatch { declared: usize, actual: usize },
+    /// The header length is not a multiple of 4.
+    UnalignedLength(usize),
+    /// An attribute value does not have the size its type requires.
+    InvalidLength { expected: usize, actual: usize },
+    /// The magic cookie is not 0x2112A442 where RFC 5389 requires it.
+    BadMagicCookie(u32),
+    /// The two most significant bits of the message type are not zero.
+    InvalidMessageType(u32),
+    InvalidClass(u32),
+    ReservedMethod(u32),
+    UnknownMethod(u32),
+    ReservedAttribute(u32),
+    UnknownAttribute(u32),
+    InvalidFamily(u32),
+    InvalidErrorCode(u32),
+    InvalidUtf8,
+    /// A string attribute longer than RFC 5389 allows.
+    TooLong { max: usize, actual: usize },
+    /// A string rejected by SASLprep (RFC 4013) or OpaqueString (RFC 8265).
+    StringPrep,
+    /// A malformed attribute, `offset` is the position of its type in the message.
+    Attribute { typ: u16, offset: usize, error: Box<Error> },
+    /// The FINGERPRINT attribute is not the last one.
+    FingerprintNotLast,
+    FingerprintMismatch { expected: u32, actual: u32 },
+    MissingIntegrity,
+    IntegrityCheckFailure,
+    /// PASSWORD-ALGORITHMS / PASSWORD-ALGORITHM do not match what the server
+    /// offered, the request may have been bid down (RFC 8489 9.2.4).
+    PasswordAlgorithmMismatch,
+    /// A ChannelData channel number outside 0x4000-0x7FFF.
+    InvalidChannelNumber(u32),
+    /// The output buffer cannot hold the message being built.
+    BufferTooSmall { capacity: usize, needed: usize },
+    /// A message body or an attribute value longer than its 16 bits length field allows.
+    MessageTooLarge { max: usize, actual: usize },
+    /// The peer answered with an error response.
+    ErrorResponse { code: ErrorCode, reason: String },
+    /// A response lacks an attribute it must carry (the attribute type).
+    MissingAttribute(u32),
+    /// No response to a request after all its retransmissions.
+    Timeout,
+    /// A request must be sent at least once (RFC 5389 Rc).
+    InvalidRetransmissionCount(u32),
+    /// A wait too long for the clock to tell when it ends.
+    DurationTooLong(Duration),
+    /// The server answered from another address than CHANGE-REQUEST asked
+    /// for (the address in RESPONSE-ORIGIN).
+    UnexpectedResponseOrigin(SocketAddr),
+    InvalidUri(String),
+    ServerNotSet,
+    Io(io::Error)
+}
+
+impl Error {
+    /// The ERROR-CODE a server should answer a request failing with this error.
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            Error::Attribute { ref error, .. }   => error.error_code(),
+            Error::UnknownAttribute(n) if n < 0x8000 => ErrorCode::UnknownAttribute,
+            Error::IntegrityCheckFailure         => ErrorCode::Unauthorized,
+            Error::ErrorResponse { ref code, .. } => code.clone(),
+            Error::InvalidUri(_)
+            | Error::BufferTooSmall { .. }
+            | Error::MessageTooLarge { .. }
+            | Error::ServerNotSet
+            | Error::Timeout
+            | Error::InvalidRetransmissionCount(_)
+            | Error::DurationTooLong(_)
+            | Error::Io(_)                       => ErrorCode::ServerError,
+            _                                    => ErrorCode::BadRequest
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Truncated { offset, needed } => write!(f, "truncated input: {} more bytes needed at offset {}", needed, offset),
+            Error::LengthMismatch { declared, actual } => write!(f, "message length {} does not match the body size {}", declared, actual),
+            Error::UnalignedLength(length)  => write!(f, "message length {} is not a multiple of 4", length),
+            Error::InvalidLength { expected, actual } => write!(f, "attribute value length {} (expected {})", actual, expected),
+            Error::BadMagicCookie(cookie)   => write!(f, "bad magic cookie {:#010X}", cookie),
+            Error::InvalidMessageType(n)    => write!(f, "invalid message type {:#06X}", n),
+            Error::InvalidClass(n)          => write!(f, "invalid message class {:#04b}", n),
+            Error::ReservedMethod(n)        => write!(f, "reserved message method {:#05X}", n),
+            Error::UnknownMethod(n)         => write!(f, "unknown message method {:#05X}", n),
+            Error::ReservedAttribute(n)     => write!(f, "reserved attribute type {:#06X}", n),
+            Error::UnknownAttribute(n)      => write!(f, "unknown attribute type {:#06X}", n),
+            Error::InvalidFamily(n)         => write!(f, "invalid address family {:#04X}", n),
+            Error::InvalidErrorCode(n)      => write!(f, "invalid error code {}", n),
+            Error::InvalidUtf8              => write!(f, "attribute value is not a valid UTF-8 string"),
+            Error::TooLong { max, actual }  => write!(f, "attribute value too long: {} (at most {})", actual, max),
+            Error::StringPrep               => write!(f, "string rejected by SASLprep / OpaqueString"),
+            Error::Attribute { typ, offset, ref error } => write!(f, "attribute {:#06X} at offset {}: {}", typ, offset, error),
+            Error::FingerprintNotLast       => write!(f, "FINGERPRINT must be the last attribute"),
+            Error::FingerprintMismatch { expected, actual } => write!(f, "FINGERPRINT mismatch: {:#010X} (expected {:#010X})", actual, expected),
+            Error::MissingIntegrity         => write!(f, "MESSAGE-INTEGRITY attribute missing"),
+            Error::IntegrityCheckFailure    => write!(f, "MESSAGE-INTEGRITY check failure"),
+            Error::PasswordAlgorithmMismatch => write!(f, "PASSWORD-ALGORITHM mismatch"),
+            Error::InvalidChannelNumber(n)  => write!(f, "invalid channel number {:#06X}", n),
+            Error::BufferTooSmall { capacity, needed } => write!(f, "buffer of {} bytes too small, {} bytes needed", capacity, needed),
+            Error::MessageTooLarge { max, actual } => write!(f, "message too large: {} bytes (at most {})", actual, max),
+            Error::ErrorResponse { ref code, ref reason } => write!(f, "error response {}: {}", code.to_u32(), reason),
+            Error::MissingAttribute(n)      => write!(f, "attribute {:#06X} missing", n),
+            Error::Timeout                  => write!(f, "no response received"),
+            Error::InvalidRetransmissionCount(rc) => write!(f, "invalid retransmission count {}", rc),
+            Error::DurationTooLong(duration) => write!(f, "duration too long: {:?}", duration),
+            Error::UnexpectedResponseOrigin(ref origin) => write!(f, "response sent from unexpected address {}", origin),
+            Error::InvalidUri(ref uri)      => write!(f, "invalid STUN uri {:?}", uri),
+            Error::ServerNotSet             => write!(f, "STUN server uri not set"),
+            Error::Io(ref e)                => write!(f, "I/O error: {}", e)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Attribute { ref error, .. } => Some(&**error),
+            Error::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
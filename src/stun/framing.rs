@@ -0,0 +1,180 @@
+use std::io::{Read, Write};
+
+use super::packet::{HEADER_SIZE, padding_len};
+use super::packet::channel_data::CHANNEL_DATA_HEADER_SIZE;
+use super::error::Error;
+
+/// Size of the RFC 4571 length prefix.
+pub const LENGTH_PREFIX_SIZE: usize = 2;
+
+/**
+How messages are delimited on a stream transport (TCP, TLS).
+
+`Native`: STUN messages and TURN ChannelData messages are sent back to
+back, a STUN message is delimited by the length in its header and a
+ChannelData message by its length field, rounded up to a multiple of 4
+since ChannelData is always padded over TCP (RFC 5766 11.5). The two most
+significant bits of the first byte tell them apart: 0b00 for STUN, 0b01
+for ChannelData.
+
+`Rfc4571`: every frame is preceded by its length as a 16 bits unsigned
+integer, as ICE-TCP candidates do (RFC 6544 3).
+    0                   1                   2                   3
+    0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    -----------------------------------------------------------------
+    |             LENGTH            |  STUN / RTP / ... packet      |
+    -----------------------------------------------------------------
+
+Refs:
+    https://tools.ietf.org/html/rfc5389#section-7.2.2
+    https://tools.ietf.org/html/rfc5766#section-11.5
+    https://tools.ietf.org/html/rfc4571#section-2
+**/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Native,
+    Rfc4571
+}
+
+/**
+Reassemble the messages of a stream from the chunks read off it: feed
+every chunk to `extend`, then call `next_frame` until it returns `None`.
+Chunks may hold a part of a message or several messages.
+
+The frames are returned without their framing (the RFC 4571 length prefix
+or the ChannelData padding), ready for `Packet::from_bytes` or
+`ChannelData::from_bytes`.
+**/
+#[derive(Debug, Clone)]
+pub struct StreamDecoder {
+    framing: Framing,
+    buffer : Vec<u8>
+}
+
+impl StreamDecoder {
+    pub fn new(framing: Framing) -> Self {
+        StreamDecoder { framing, buffer: Vec::new() }
+    }
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+    /// The number of bytes received but not returned in a frame yet.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+    /// The next complete frame, `None` until enough bytes have been received.
+    /// An error means the stream is out of sync and should be closed.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let (start, end, size) = match self.frame_bounds()? {
+            Some(bounds) if bounds.2 <= self.buffer.len() => bounds,
+            _ => return Ok(None)
+        };
+        let frame = self.buffer[start..end].to_vec();
+        self.buffer.drain(..size);
+        Ok(Some(frame))
+    }
+    /// How many more bytes the frame being received needs.
+    pub fn missing(&self) -> usize {
+        let size = match self.frame_bounds() {
+            Ok(Some(bounds)) => bounds.2,
+            Ok(None) => match self.framing {
+                Framing::Rfc4571 => LENGTH_PREFIX_SIZE,
+                Framing::Native  => 4
+            },
+            Err(_) => return 0
+        };
+        size.saturating_sub(self.buffer.len())
+    }
+    /// Where the next frame starts and ends in the buffer and its size with
+    /// its framing, `None` until its header has been received.
+    fn frame_bounds(&self) -> Result<Option<(usize, usize, usize)>, Error> {
+        let buffer = &self.buffer;
+        match self.framing {
+            Framing::Rfc4571 => {
+                if buffer.len() < LENGTH_PREFIX_SIZE {
+                    return Ok(None);
+                }
+                let length = ((buffer[0] as usize) << 8) | buffer[1] as usize;
+                let end    = LENGTH_PREFIX_SIZE + length;
+                Ok(Some((LENGTH_PREFIX_SIZE, end, end)))
+            },
+            Framing::Native => {
+                if buffer.len() < 4 {
+                    return Ok(None);
+                }
+                let length = ((buffer[2] as usize) << 8) | buffer[3] as usize;
+                match buffer[0] >> 6 {
+                    0b00 if length % 4 != 0 => Err(Error::UnalignedLength(length)),
+                    0b00 => Ok(Some((0, HEADER_SIZE + length, HEADER_SIZE + length))),
+                    0b01 => {
+                        let end = CHANNEL_DATA_HEADER_SIZE + length;
+                        Ok(Some((0, end, end + padding_len(length))))
+                    },
+                    _ => Err(Error::InvalidMessageType(((buffer[0] as u32) << 8) | buffer[1] as u32))
+                }
+            }
+        }
+    }
+}
+
+/// Read whole frames off a stream.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner  : R,
+    decoder: StreamDecoder
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(inner: R, framing: Framing) -> Self {
+        FrameReader { inner, decoder: StreamDecoder::new(framing) }
+    }
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+    /// The next frame, blocking until it has been received. `None` when the
+    /// stream ends between two frames, `Error::Truncated` when it ends inside one.
+    pub fn read_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut buf = [0u8; 2048];
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(Some(frame));
+            }
+            let size = self.inner.read(&mut buf)?;
+            if size == 0 {
+                let pending = self.decoder.pending();
+                if pending == 0 {
+                    return Ok(None);
+                }
+                return Err(Error::Truncated { offset: pending, needed: self.decoder.missing() });
+            }
+            self.decoder.extend(&buf[..size]);
+        }
+    }
+}
+
+/// Write `frame` to `writer` with the framing `framing` needs: the length
+/// prefix for RFC 4571, the padding of a ChannelData message for `Native`.
+pub fn write_frame<W: Write>(writer: &mut W, framing: Framing, frame: &[u8]) -> Result<(), Error> {
+    match framing {
+        Framing::Rfc4571 => {
+            if frame.len() > u16::MAX as usize {
+                return Err(Error::TooLong { max: u16::MAX as usize, actual: frame.len() });
+            }
+            writer.write_all(&(frame.len() as u16).to_be_bytes())?;
+            writer.write_all(frame)?;
+        },
+        Framing::Native => {
+            writer.write_all(frame)?;
+            if frame.first().map_or(false, |b| b >> 6 == 0b01) {
+                writer.write_all(&[0u8; 3][..padding_len(frame.len())])?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -13,8 +13,12 @@ pub mod client;
 pub mod server;
 pub mod constant;
 pub mod urlparse;
+pub mod error;
+pub mod demux;
+pub mod framing;
 
 pub use self::constant::{STUN_PORT, STUNS_PORT, PUBLIC_STUN_SERVERS};
-pub use self::urlparse::url_parse;
+pub use self::urlparse::{url_parse, url_parse_with_default_port};
+pub use self::error::Error;
 // pub use self::client::Client;
 
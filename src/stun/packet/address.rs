@@ -1,34 +1,41 @@
 
 use std::str::FromStr;
+use std::fmt;
 use std::string::ToString;
+use std::io::{self, Write};
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::super::error::Error;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 /**
 The address family can take on the following values:
-    
     0x01:IPv4
     0x02:IPv6
 **/
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Family {
     Ipv4,
     Ipv6
 }
 
-impl ToString for Family {
-    fn to_string(&self) -> String {
-        match *self {
-            Family::Ipv4 => "IPv4".to_owned(),
-            Family::Ipv6 => "IPv6".to_owned()
-        }
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Family::Ipv4 => "IPv4",
+            Family::Ipv6 => "IPv6"
+        })
     }
 }
 
 impl Family {
-    pub fn from_u32(n: u32) -> Result<Self, &'static str> {
+    pub fn from_u32(n: u32) -> Result<Self, Error> {
         match n {
             0x01 => Ok(Family::Ipv4),
             0x02 => Ok(Family::Ipv6),
-            _    => Err("Address Family Error")
+            _    => Err(Error::InvalidFamily(n))
         }
     }
     pub fn to_u32(&self) -> u32 {
@@ -39,15 +46,103 @@ impl Family {
     }
 }
 
-#[derive(Debug)]
+/**
+0                   1                   2                   3
+0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|0 0 0 0 0 0 0 0|    Family     |           Port                |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                                                               |
+|                 Address (32 bits or 128 bits)                 |
+|                                                               |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+Figure 5: Format of MAPPED-ADDRESS Attribute
+
+The binary layout shared by MAPPED-ADDRESS, ALTERNATE-SERVER, RESPONSE-ORIGIN,
+OTHER-ADDRESS and the legacy RFC 3489 address attributes. The XOR-* attributes
+use the same layout once the port and address have been XOR'ed.
+**/
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Address {
     family : Family,
     port   : u16,
-    address: String
+    address: IpAddr
 }
 
 impl Address {
-    pub fn new (family: Family, port: u16, address: String) {
+    pub fn new (family: Family, port: u16, address: IpAddr) -> Result<Self, Error> {
+        match (&family, &address) {
+            (&Family::Ipv4, &IpAddr::V4(_)) | (&Family::Ipv6, &IpAddr::V6(_)) => Ok(Address {
+                family,
+                port,
+                address
+            }),
+            _ => Err(Error::InvalidFamily(family.to_u32()))
+        }
+    }
+    pub fn family(&self) -> &Family {
+        &self.family
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn ip(&self) -> IpAddr {
+        self.address
+    }
+    pub fn to_socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 4 {
+            return Err(Error::Truncated { offset: bytes.len(), needed: 4 - bytes.len() });
+        }
+        let family = Family::from_u32(bytes[1] as u32)?;
+        let port   = ((bytes[2] as u16) << 8) | bytes[3] as u16;
+        let address = match family {
+            Family::Ipv4 => {
+                if bytes.len() != 8 {
+                    return Err(Error::InvalidLength { expected: 8, actual: bytes.len() });
+                }
+                IpAddr::V4(Ipv4Addr::new(bytes[4], bytes[5], bytes[6], bytes[7]))
+            },
+            Family::Ipv6 => {
+                if bytes.len() != 20 {
+                    return Err(Error::InvalidLength { expected: 20, actual: bytes.len() });
+                }
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&bytes[4..20]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+        };
+        Address::new(family, port, address)
+    }
+    pub fn into_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(20);
+        self.write_to(&mut bytes).unwrap();
+        bytes
+    }
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[0, self.family.to_u32() as u8])?;
+        writer.write_all(&self.port.to_be_bytes())?;
+        match self.address {
+            IpAddr::V4(ip) => writer.write_all(&ip.octets()),
+            IpAddr::V6(ip) => writer.write_all(&ip.octets())
+        }
+    }
+}
 
+impl From<SocketAddr> for Address {
+    fn from(socket_addr: SocketAddr) -> Self {
+        let family = match socket_addr {
+            SocketAddr::V4(_) => Family::Ipv4,
+            SocketAddr::V6(_) => Family::Ipv6
+        };
+        Address {
+            family,
+            port   : socket_addr.port(),
+            address: socket_addr.ip()
+        }
     }
 }
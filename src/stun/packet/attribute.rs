@@ -1,9 +1,16 @@
 
 use std::str::FromStr;
+use std::fmt;
 use std::string::ToString;
-use std::net::SocketAddr;
+use std::io::{self, Write};
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
 
-use super::ErrorCode;
+use super::{ErrorCode, Family, Address, Header};
+use super::integrity::PasswordAlgorithm;
+use super::super::constant::STUN_MAGIC_COOKIE;
+use super::super::error::Error;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 /**
 Range:
@@ -40,7 +47,10 @@ Range:
 0x0019  REQUESTED-TRANSPORT [RFC5766]
 0x001A  DONT-FRAGMENT   [RFC5766]
 0x001B  ACCESS-TOKEN    [RFC7635]
-0x001C-0x001F   Unassigned  
+0x001C  MESSAGE-INTEGRITY-SHA256    [RFC8489]
+0x001D  PASSWORD-ALGORITHM  [RFC8489]
+0x001E  USERHASH    [RFC8489]
+0x001F  Unassigned  
 0x0020  XOR-MAPPED-ADDRESS  [RFC5389]
 0x0021  Reserved (was TIMER-VAL)    [RFC5766]
 0x0022  RESERVATION-TOKEN   [RFC5766]
@@ -54,7 +64,9 @@ Range:
 0x002B-0x002F   Unassigned  
 0x0030  Reserved    
 0x0031-0x7FFF   Unassigned  
-0x8000-0x8021   Unassigned  
+0x8000-0x8001   Unassigned  
+0x8002  PASSWORD-ALGORITHMS [RFC8489]
+0x8003-0x8021   Unassigned  
 0x8022  SOFTWARE    [RFC5389]
 0x8023  ALTERNATE-SERVER    [RFC5389]
 0x8024  Reserved    
@@ -93,17 +105,18 @@ Range:
 0x8024  Reserved   
 0x8026  Reserved  
 
-0x001C-0x001F   Unassigned 
+0x001F   Unassigned 
 0x002B-0x002F   Unassigned 
 0x0031-0x7FFF   Unassigned  
-0x8000-0x8021   Unassigned   
+0x8000-0x8001   Unassigned   
+0x8003-0x8021   Unassigned   
 0x802F  Unassigned   
 0x8031-0xBFFF   Unassigned  
 0xC003-0xFFFF   Unassigned  
 **/
 
 // Message Attribute Type
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AttributeType {
     MappedAddress,    // 0x0001  MAPPED-ADDRESS  [RFC5389]
     ResponseAddress,  // 0x0002  Reserved; was RESPONSE-ADDRESS  [RFC5389]
@@ -129,6 +142,9 @@ pub enum AttributeType {
     RequestedTransport,   // 0x0019  REQUESTED-TRANSPORT [RFC5766]
     DontFragment,         // 0x001A  DONT-FRAGMENT   [RFC5766]
     AccessToken,          // 0x001B  ACCESS-TOKEN    [RFC7635]
+    MessageIntegritySha256, // 0x001C  MESSAGE-INTEGRITY-SHA256    [RFC8489]
+    PasswordAlgorithm,    // 0x001D  PASSWORD-ALGORITHM  [RFC8489]
+    UserHash,             // 0x001E  USERHASH    [RFC8489]
     XorMappedAddress,     // 0x0020  XOR-MAPPED-ADDRESS  [RFC5389]
     TimerVal,             // 0x0021  Reserved (was TIMER-VAL)    [RFC5766]
     ReservationToken,     // 0x0022  RESERVATION-TOKEN   [RFC5766]
@@ -137,6 +153,7 @@ pub enum AttributeType {
     Padding,              // 0x0026  PADDING [RFC5780]
     ResponsePort,         // 0x0027  RESPONSE-PORT   [RFC5780]
     ConnectionID,         // 0x002A  CONNECTION-ID   [RFC6062]
+    PasswordAlgorithms,   // 0x8002  PASSWORD-ALGORITHMS [RFC8489]
     Software,             // 0x8022  SOFTWARE    [RFC5389]
     AlternateServer,      // 0x8023  ALTERNATE-SERVER    [RFC5389]
     TransactionTransmitCounter, // 0x8025  TRANSACTION_TRANSMIT_COUNTER    [RFC7982]
@@ -154,78 +171,83 @@ pub enum AttributeType {
     ENFNetworkStatus            // 0xC002  ENF-NETWORK-STATUS  [Pål_Erik_Martinsen]
 }
 
-impl ToString for AttributeType {
-    fn to_string (&self) -> String {
-        match *self {
-            AttributeType::MappedAddress => "MAPPED-ADDRESS".to_owned(),
-            AttributeType::ResponseAddress => "RESPONSE-ADDRESS".to_owned(),
-            AttributeType::ChangeRequest => "CHANGE-REQUEST".to_owned(),
-            AttributeType::SourceAddress => "SOURCE-ADDRESS".to_owned(),
-            AttributeType::ChangedAddress => "CHANGED-ADDRESS".to_owned(),
-            AttributeType::UserName => "USERNAME".to_owned(),
-            AttributeType::Password => "PASSWORD".to_owned(),
-            AttributeType::MessageIntegrity => "MESSAGE-INTEGRITY".to_owned(),
-            AttributeType::ErrorCode => "ERROR-CODE".to_owned(),
-            AttributeType::UnknownAttribute => "UNKNOWN-ATTRIBUTES".to_owned(),
-            AttributeType::ReflectedFrom => "REFLECTED-FROM".to_owned(),
-            AttributeType::ChannelNumber => "CHANNEL-NUMBER".to_owned(),
-            AttributeType::LifeTime => "LIFETIME".to_owned(),
-            AttributeType::BandWidth => "BANDWIDTH".to_owned(),
-            AttributeType::XorPeerAddress => "XOR-PEER-ADDRESS".to_owned(),
-            AttributeType::Data => "DATA".to_owned(),
-            AttributeType::Realm => "REALM".to_owned(),
-            AttributeType::Nonce => "NONCE".to_owned(),
-            AttributeType::XorRelayedAddress => "XOR-RELAYED-ADDRESS".to_owned(),
-            AttributeType::RequestAddressFamily => "REQUESTED-ADDRESS-FAMILY".to_owned(),
-            AttributeType::EvenPort => "EVEN-PORT".to_owned(),
-            AttributeType::RequestedTransport => "REQUESTED-TRANSPORT".to_owned(),
-            AttributeType::DontFragment => "DONT-FRAGMENT".to_owned(),
-            AttributeType::AccessToken => "ACCESS-TOKEN".to_owned(),
-            AttributeType::XorMappedAddress => "XOR-MAPPED-ADDRESS".to_owned(),
-            AttributeType::TimerVal => "TIMER-VAL".to_owned(),
-            AttributeType::ReservationToken => "RESERVATION-TOKEN".to_owned(),
-            AttributeType::Priority => "PRIORITY".to_owned(),
-            AttributeType::UseCandidate => "USE-CANDIDATE".to_owned(),
-            AttributeType::Padding => "PADDING".to_owned(),
-            AttributeType::ResponsePort => "RESPONSE-PORT".to_owned(),
-            AttributeType::ConnectionID => "CONNECTION-ID".to_owned(),
-            AttributeType::Software => "SOFTWARE".to_owned(),
-            AttributeType::AlternateServer => "ALTERNATE-SERVER".to_owned(),
-            AttributeType::TransactionTransmitCounter => "TRANSACTION_TRANSMIT_COUNTER".to_owned(),
-            AttributeType::CacheTimeout => "CACHE-TIMEOUT".to_owned(),
-            AttributeType::FingerPrint => "FINGERPRINT".to_owned(),
-            AttributeType::ICEControlled => "ICE-CONTROLLED".to_owned(),
-            AttributeType::ICEControlling => "ICE-CONTROLLING".to_owned(),
-            AttributeType::ResponseOrigin => "RESPONSE-ORIGIN".to_owned(),
-            AttributeType::OtherAddress => "OTHER-ADDRESS".to_owned(),
-            AttributeType::ECNCheckStun => "ECN-CHECK STUN".to_owned(),
-            AttributeType::ThirdPartyAuthorization => "THIRD-PARTY-AUTHORIZATION".to_owned(),
-            AttributeType::MobilityTicket => "MOBILITY-TICKET".to_owned(),
-            AttributeType::CiscoStunFlowData => "CISCO-STUN-FLOWDATA".to_owned(),
-            AttributeType::ENFFlowDescription => "ENF-FLOW-DESCRIPTION".to_owned(),
-            AttributeType::ENFNetworkStatus => "ENF-NETWORK-STATUS".to_owned()
-        }
+impl fmt::Display for AttributeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AttributeType::MappedAddress => "MAPPED-ADDRESS",
+            AttributeType::ResponseAddress => "RESPONSE-ADDRESS",
+            AttributeType::ChangeRequest => "CHANGE-REQUEST",
+            AttributeType::SourceAddress => "SOURCE-ADDRESS",
+            AttributeType::ChangedAddress => "CHANGED-ADDRESS",
+            AttributeType::UserName => "USERNAME",
+            AttributeType::Password => "PASSWORD",
+            AttributeType::MessageIntegrity => "MESSAGE-INTEGRITY",
+            AttributeType::ErrorCode => "ERROR-CODE",
+            AttributeType::UnknownAttribute => "UNKNOWN-ATTRIBUTES",
+            AttributeType::ReflectedFrom => "REFLECTED-FROM",
+            AttributeType::ChannelNumber => "CHANNEL-NUMBER",
+            AttributeType::LifeTime => "LIFETIME",
+            AttributeType::BandWidth => "BANDWIDTH",
+            AttributeType::XorPeerAddress => "XOR-PEER-ADDRESS",
+            AttributeType::Data => "DATA",
+            AttributeType::Realm => "REALM",
+            AttributeType::Nonce => "NONCE",
+            AttributeType::XorRelayedAddress => "XOR-RELAYED-ADDRESS",
+            AttributeType::RequestAddressFamily => "REQUESTED-ADDRESS-FAMILY",
+            AttributeType::EvenPort => "EVEN-PORT",
+            AttributeType::RequestedTransport => "REQUESTED-TRANSPORT",
+            AttributeType::DontFragment => "DONT-FRAGMENT",
+            AttributeType::AccessToken => "ACCESS-TOKEN",
+            AttributeType::MessageIntegritySha256 => "MESSAGE-INTEGRITY-SHA256",
+            AttributeType::PasswordAlgorithm => "PASSWORD-ALGORITHM",
+            AttributeType::UserHash => "USERHASH",
+            AttributeType::XorMappedAddress => "XOR-MAPPED-ADDRESS",
+            AttributeType::TimerVal => "TIMER-VAL",
+            AttributeType::ReservationToken => "RESERVATION-TOKEN",
+            AttributeType::Priority => "PRIORITY",
+            AttributeType::UseCandidate => "USE-CANDIDATE",
+            AttributeType::Padding => "PADDING",
+            AttributeType::ResponsePort => "RESPONSE-PORT",
+            AttributeType::ConnectionID => "CONNECTION-ID",
+            AttributeType::PasswordAlgorithms => "PASSWORD-ALGORITHMS",
+            AttributeType::Software => "SOFTWARE",
+            AttributeType::AlternateServer => "ALTERNATE-SERVER",
+            AttributeType::TransactionTransmitCounter => "TRANSACTION_TRANSMIT_COUNTER",
+            AttributeType::CacheTimeout => "CACHE-TIMEOUT",
+            AttributeType::FingerPrint => "FINGERPRINT",
+            AttributeType::ICEControlled => "ICE-CONTROLLED",
+            AttributeType::ICEControlling => "ICE-CONTROLLING",
+            AttributeType::ResponseOrigin => "RESPONSE-ORIGIN",
+            AttributeType::OtherAddress => "OTHER-ADDRESS",
+            AttributeType::ECNCheckStun => "ECN-CHECK STUN",
+            AttributeType::ThirdPartyAuthorization => "THIRD-PARTY-AUTHORIZATION",
+            AttributeType::MobilityTicket => "MOBILITY-TICKET",
+            AttributeType::CiscoStunFlowData => "CISCO-STUN-FLOWDATA",
+            AttributeType::ENFFlowDescription => "ENF-FLOW-DESCRIPTION",
+            AttributeType::ENFNetworkStatus => "ENF-NETWORK-STATUS"
+        })
     }
 }
 
 impl AttributeType {
-    pub fn from_u32 (n: u32) -> Result<Self, &'static str> {
+    pub fn from_u32 (n: u32) -> Result<Self, Error> {
         match n {
             0x0000
-            | 0x000E ... 0x000F
+            | 0x000E ..= 0x000F
             | 0x0011
             | 0x0023
-            | 0x0028 ... 0x0029
+            | 0x0028 ..= 0x0029
             | 0x0030
             | 0x8024
-            | 0x8026 => Err("Reserved"),
-            0x001C ... 0x001F
-            | 0x002B ... 0x002F
-            | 0x0031 ... 0x7FFF
-            | 0x8000 ... 0x8021
+            | 0x8026 => Err(Error::ReservedAttribute(n)),
+            0x001F
+            | 0x002B ..= 0x002F
+            | 0x0031 ..= 0x7FFF
+            | 0x8000 ..= 0x8001
+            | 0x8003 ..= 0x8021
             | 0x802F
-            | 0x8031 ... 0xBFFF
-            | 0xC003 ... 0xFFFF => Err("Unassigned"),
+            | 0x8031 ..= 0xBFFF
+            | 0xC003 ..= 0xFFFF => Err(Error::UnknownAttribute(n)),
             0x0001 => Ok(AttributeType::MappedAddress),
             0x0002 => Ok(AttributeType::ResponseAddress),
             0x0003 => Ok(AttributeType::ChangeRequest),
@@ -250,6 +272,9 @@ impl AttributeType {
             0x0019 => Ok(AttributeType::RequestedTransport),
             0x001A => Ok(AttributeType::DontFragment),
             0x001B => Ok(AttributeType::AccessToken),
+            0x001C => Ok(AttributeType::MessageIntegritySha256),
+            0x001D => Ok(AttributeType::PasswordAlgorithm),
+            0x001E => Ok(AttributeType::UserHash),
             0x0020 => Ok(AttributeType::XorMappedAddress),
             0x0021 => Ok(AttributeType::TimerVal),
             0x0022 => Ok(AttributeType::ReservationToken),
@@ -258,6 +283,7 @@ impl AttributeType {
             0x0026 => Ok(AttributeType::Padding),
             0x0027 => Ok(AttributeType::ResponsePort),
             0x002A => Ok(AttributeType::ConnectionID),
+            0x8002 => Ok(AttributeType::PasswordAlgorithms),
             0x8022 => Ok(AttributeType::Software),
             0x8023 => Ok(AttributeType::AlternateServer),
             0x8025 => Ok(AttributeType::TransactionTransmitCounter),
@@ -273,16 +299,14 @@ impl AttributeType {
             0xC000 => Ok(AttributeType::CiscoStunFlowData),
             0xC001 => Ok(AttributeType::ENFFlowDescription),
             0xC002 => Ok(AttributeType::ENFNetworkStatus),
-            _      => Err("Range(0x0000 ... 0xFFFF and ).")
+            _      => Err(Error::UnknownAttribute(n))
         }
     }
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
-        let hex_str = bytes[..2].iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join("");
-        let number  = match u32::from_str_radix(hex_str.as_str(), 16){
-            Ok(number) => number,
-            Err(_) => return Err("Attribute Type parse error.")
-        };
-        AttributeType::from_u32(number)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(Error::Truncated { offset: bytes.len(), needed: 2 - bytes.len() });
+        }
+        AttributeType::from_u32(read_u16(bytes) as u32)
     }
     pub fn to_u32(&self) -> u32 {
         match *self {
@@ -310,6 +334,9 @@ impl AttributeType {
             AttributeType::RequestedTransport   => 0x0019,
             AttributeType::DontFragment         => 0x001A,
             AttributeType::AccessToken          => 0x001B,
+            AttributeType::MessageIntegritySha256 => 0x001C,
+            AttributeType::PasswordAlgorithm    => 0x001D,
+            AttributeType::UserHash             => 0x001E,
             AttributeType::XorMappedAddress     => 0x0020,
             AttributeType::TimerVal             => 0x0021,
             AttributeType::ReservationToken     => 0x0022,
@@ -318,6 +345,7 @@ impl AttributeType {
             AttributeType::Padding              => 0x0026,
             AttributeType::ResponsePort         => 0x0027,
             AttributeType::ConnectionID         => 0x002A,
+            AttributeType::PasswordAlgorithms   => 0x8002,
             AttributeType::Software             => 0x8022,
             AttributeType::AlternateServer      => 0x8023,
             AttributeType::TransactionTransmitCounter => 0x8025,
@@ -337,92 +365,568 @@ impl AttributeType {
     }
 }
 
-#[derive(Debug)]
-pub enum Attribute {
-    MappedAddress(SocketAddr),
-    XorMappedAddress(SocketAddr),
-    ResponseAddress(SocketAddr),
-    ResponseOrigin(SocketAddr),
-    XorPeerAddress(SocketAddr),
-    UserName,
-    ErrorCode(ErrorCode),
-    UnknownAttribute,
-    ReflectedFrom,
+/// Attribute values are aligned on 32-bit boundaries,
+/// returns the number of padding bytes following a value of `length` bytes.
+pub fn padding_len(length: usize) -> usize {
+    (4 - length % 4) % 4
 }
 
-impl Attribute {
-    pub fn from_bytes(attr_type: AttributeType, bytes: &[u8]) -> Result<Self, &'static str>{
-        unimplemented!();
+fn expect_length(bytes: &[u8], length: usize) -> Result<(), Error> {
+    if bytes.len() != length {
+        return Err(Error::InvalidLength { expected: length, actual: bytes.len() });
     }
-    pub fn into_bytes(&self) -> Vec<u8> {
-        /**
-            type  : AttributeType,  // 16 bits
-            length: u32,            // 16 bits
-            value : Attribute       // 32 bits ( Or More. )
-        **/
-        match *self {
-            Attribute::MappedAddress(ref socket_addr) => {
-                // type
-                let attribute_type = 0x0001u16;
-                let attribute_type_bits = format!("{:016b}", attribute_type );
+    Ok(())
+}
 
-                let mut bytes: Vec<u8> = vec![
-                    u8::from_str_radix(&attribute_type_bits[0.. 8], 2).unwrap(),
-                    u8::from_str_radix(&attribute_type_bits[8..16], 2).unwrap()
-                ];
-                
-                // value
-                let family = match *socket_addr {
-                    SocketAddr::V4(_) => 0x01u8,
-                    SocketAddr::V6(_) => 0x02u8
-                };
-                let port: u16 = socket_addr.port();
-                let address = format!("{}", socket_addr.ip());
-                
-                let mut attribute_value: Vec<u8> = vec![0, family];
-                let port_bits = format!("{:016b}", port);
+fn read_u16(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 8) | bytes[1] as u16
+}
 
-                attribute_value.push(u8::from_str_radix(&port_bits[0.. 8], 2).unwrap());
-                attribute_value.push(u8::from_str_radix(&port_bits[8..16], 2).unwrap());
-                attribute_value.extend(address.into_bytes());
+fn read_u32(bytes: &[u8]) -> u32 {
+    ((read_u16(&bytes[0..2]) as u32) << 16) | read_u16(&bytes[2..4]) as u32
+}
 
-                // length
-                let attribute_length_bits = format!("{:016b}", attribute_value.len() as u16 );
+fn read_u64(bytes: &[u8]) -> u64 {
+    ((read_u32(&bytes[0..4]) as u64) << 32) | read_u32(&bytes[4..8]) as u64
+}
 
-                bytes.push(u8::from_str_radix(&attribute_type_bits[0.. 8], 2).unwrap());
-                bytes.push(u8::from_str_radix(&attribute_type_bits[8..16], 2).unwrap());
+fn read_string(bytes: &[u8]) -> Result<String, Error> {
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(s)  => Ok(s),
+        Err(_) => Err(Error::InvalidUtf8)
+    }
+}
 
-                bytes.extend(attribute_value);
-                bytes
-            },
-            Attribute::ErrorCode(ref error_code) => {
-                let attribute_type = 0x0009u16;
-                let attribute_type_bits = format!("{:016b}", attribute_type );
+/// USERNAME MUST be less than 513 bytes (RFC 5389 15.3).
+pub const MAX_USERNAME_BYTES: usize = 512;
+/// REALM, NONCE, SOFTWARE and the ERROR-CODE reason phrase MUST be less than
+/// 128 characters, which can be as long as 763 bytes (RFC 5389 15).
+pub const MAX_TEXT_CHARS: usize = 127;
+pub const MAX_TEXT_BYTES: usize = 763;
 
-                let mut bytes: Vec<u8> = vec![
-                    u8::from_str_radix(&attribute_type_bits[0.. 8], 2).unwrap(),
-                    u8::from_str_radix(&attribute_type_bits[8..16], 2).unwrap()
-                ];
+fn check_text(s: &str) -> Result<(), Error> {
+    if s.len() > MAX_TEXT_BYTES {
+        return Err(Error::TooLong { max: MAX_TEXT_BYTES, actual: s.len() });
+    }
+    let chars = s.chars().count();
+    if chars > MAX_TEXT_CHARS {
+        return Err(Error::TooLong { max: MAX_TEXT_CHARS, actual: chars });
+    }
+    Ok(())
+}
 
-                // value
-                let mut attribute_value:Vec<u8>  = vec![0, 0];
-                let code   = error_code.to_u32();
-                let class  = (code/100) as u8; // 3 bits
-                let number = (code%100) as u8; // 8 bits
-                attribute_value.push(u8::from_str_radix(format!("{:02x}", class).as_str(), 10).unwrap());
-                attribute_value.push(u8::from_str_radix(format!("{:02x}", number).as_str(), 10).unwrap());
-                attribute_value.extend(error_code.to_bytes());
+fn decode_address(bytes: &[u8]) -> Result<SocketAddr, Error> {
+    Ok(Address::from_bytes(bytes)?.to_socket_addr())
+}
 
-                // length
-                let attribute_length_bits = format!("{:016b}", attribute_value.len() as u16 );
+/// A writer which only counts the bytes written to it.
+struct Counter(usize);
 
-                bytes.push(u8::from_str_radix(&attribute_type_bits[0.. 8], 2).unwrap());
-                bytes.push(u8::from_str_radix(&attribute_type_bits[8..16], 2).unwrap());
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The XOR-MAPPED-ADDRESS family (RFC 5389 15.2) obfuscates the port with the
+/// most significant 16 bits of the magic cookie and the address with the
+/// magic cookie (IPv4) or the magic cookie concatenated with the 96-bit
+/// transaction id (IPv6). XOR is its own inverse, so this both encodes and decodes.
+fn xor_address(socket_addr: &SocketAddr, header: &Header) -> SocketAddr {
+    let mut key = [0u8; 16];
+    key[..4].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    key[4..].copy_from_slice(header.transaction_id().as_bytes());
+
+    let port = socket_addr.port() ^ (STUN_MAGIC_COOKIE >> 16) as u16;
+    match socket_addr.ip() {
+        IpAddr::V4(ip) => {
+            let ip = u32::from(ip) ^ STUN_MAGIC_COOKIE;
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), port)
+        },
+        IpAddr::V6(ip) => {
+            let mut octets = ip.octets();
+            for (octet, k) in octets.iter_mut().zip(key.iter()) {
+                *octet ^= *k;
+            }
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)
+        }
+    }
+}
+
+/// Attribute types 0x0000-0x7FFF are comprehension-required: a STUN agent which
+/// does not understand one of them cannot process the message (RFC 5389 15).
+/// Types 0x8000-0xFFFF are comprehension-optional and may be ignored.
+pub fn is_comprehension_required(typ: u16) -> bool {
+    typ < 0x8000
+}
+
+/// The XOR address attributes only exist in RFC 5389 messages (with the magic cookie).
+fn decode_xor_address(bytes: &[u8], header: &Header) -> Result<SocketAddr, Error> {
+    if header.magic_cookie() != STUN_MAGIC_COOKIE {
+        return Err(Error::BadMagicCookie(header.magic_cookie()));
+    }
+    Ok(xor_address(&decode_address(bytes)?, header))
+}
 
-                bytes.extend(attribute_value);
-                bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Attribute {
+    MappedAddress(SocketAddr),
+    ResponseAddress(SocketAddr),
+    ChangeRequest { change_ip: bool, change_port: bool },
+    SourceAddress(SocketAddr),
+    ChangedAddress(SocketAddr),
+    UserName(String),
+    Password(String),
+    MessageIntegrity(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] [u8; 20]),
+    /// The error code and the reason phrase sent with it.
+    #[cfg_attr(feature = "serde", serde(with = "super::serialize::error_code_attribute"))]
+    ErrorCode(ErrorCode, String),
+    UnknownAttribute(Vec<u16>),
+    ReflectedFrom(SocketAddr),
+    ChannelNumber(u16),
+    LifeTime(u32),
+    BandWidth(u32),
+    XorPeerAddress(SocketAddr),
+    Data(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    Realm(String),
+    Nonce(String),
+    XorRelayedAddress(SocketAddr),
+    RequestAddressFamily(Family),
+    EvenPort(bool),
+    RequestedTransport(u8),
+    DontFragment,
+    AccessToken(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    MessageIntegritySha256(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    PasswordAlgorithm(PasswordAlgorithm),
+    UserHash(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] [u8; 32]),
+    XorMappedAddress(SocketAddr),
+    TimerVal(u32),
+    ReservationToken(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] [u8; 8]),
+    Priority(u32),
+    UseCandidate,
+    Padding(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    ResponsePort(u16),
+    ConnectionID(u32),
+    PasswordAlgorithms(Vec<PasswordAlgorithm>),
+    Software(String),
+    AlternateServer(SocketAddr),
+    TransactionTransmitCounter { request: u8, response: u8 },
+    CacheTimeout(u32),
+    FingerPrint(u32),
+    ICEControlled(u64),
+    ICEControlling(u64),
+    ResponseOrigin(SocketAddr),
+    OtherAddress(SocketAddr),
+    ECNCheckStun { ecf: u8, valid: bool },
+    ThirdPartyAuthorization(String),
+    MobilityTicket(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    CiscoStunFlowData(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    ENFFlowDescription(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    ENFNetworkStatus(#[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] Vec<u8>),
+    Unknown { typ: u16, #[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] value: Vec<u8> }
+}
+
+impl Attribute {
+    /// Decode the value (without the type, length and padding) of an attribute,
+    /// the header of the enclosing message is needed by the XOR address attributes.
+    pub fn from_bytes(attr_type: AttributeType, bytes: &[u8], header: &Header) -> Result<Self, Error>{
+        let attribute = match attr_type {
+            AttributeType::MappedAddress   => Attribute::MappedAddress(decode_address(bytes)?),
+            AttributeType::ResponseAddress => Attribute::ResponseAddress(decode_address(bytes)?),
+            AttributeType::ChangeRequest   => {
+                expect_length(bytes, 4)?;
+                let flags = read_u32(bytes);
+                Attribute::ChangeRequest {
+                    change_ip  : flags & 0x04 != 0,
+                    change_port: flags & 0x02 != 0
+                }
+            },
+            AttributeType::SourceAddress  => Attribute::SourceAddress(decode_address(bytes)?),
+            AttributeType::ChangedAddress => Attribute::ChangedAddress(decode_address(bytes)?),
+            AttributeType::UserName       => Attribute::UserName(read_string(bytes)?),
+            AttributeType::Password       => Attribute::Password(read_string(bytes)?),
+            AttributeType::MessageIntegrity => {
+                expect_length(bytes, 20)?;
+                let mut hmac = [0u8; 20];
+                hmac.copy_from_slice(bytes);
+                Attribute::MessageIntegrity(hmac)
+            },
+            AttributeType::ErrorCode => {
+                if bytes.len() < 4 {
+                    return Err(Error::InvalidLength { expected: 4, actual: bytes.len() });
+                }
+                let class  = (bytes[2] & 0x07) as u32;
+                let number = bytes[3] as u32;
+                if number > 99 {
+                    return Err(Error::InvalidErrorCode(class * 100 + number));
+                }
+                let reason = read_string(&bytes[4..])?;
+                Attribute::ErrorCode(ErrorCode::from_code(class * 100 + number, &reason)?, reason)
+            },
+            AttributeType::UnknownAttribute => {
+                if bytes.len() % 2 != 0 {
+                    return Err(Error::InvalidLength { expected: bytes.len() + 1, actual: bytes.len() });
+                }
+                Attribute::UnknownAttribute(bytes.chunks(2).map(read_u16).collect())
+            },
+            AttributeType::ReflectedFrom => Attribute::ReflectedFrom(decode_address(bytes)?),
+            AttributeType::ChannelNumber => {
+                expect_length(bytes, 4)?;
+                Attribute::ChannelNumber(read_u16(bytes))
+            },
+            AttributeType::LifeTime => {
+                expect_length(bytes, 4)?;
+                Attribute::LifeTime(read_u32(bytes))
+            },
+            AttributeType::BandWidth => {
+                expect_length(bytes, 4)?;
+                Attribute::BandWidth(read_u32(bytes))
+            },
+            AttributeType::XorPeerAddress    => Attribute::XorPeerAddress(decode_xor_address(bytes, header)?),
+            AttributeType::XorRelayedAddress => Attribute::XorRelayedAddress(decode_xor_address(bytes, header)?),
+            AttributeType::XorMappedAddress  => Attribute::XorMappedAddress(decode_xor_address(bytes, header)?),
+            AttributeType::Data  => Attribute::Data(bytes.to_vec()),
+            AttributeType::Realm => Attribute::Realm(read_string(bytes)?),
+            AttributeType::Nonce => Attribute::Nonce(read_string(bytes)?),
+            AttributeType::RequestAddressFamily => {
+                expect_length(bytes, 4)?;
+                Attribute::RequestAddressFamily(Family::from_u32(bytes[0] as u32)?)
             },
-            _ => unimplemented!()
+            AttributeType::EvenPort => {
+                expect_length(bytes, 1)?;
+                Attribute::EvenPort(bytes[0] & 0x80 != 0)
+            },
+            AttributeType::RequestedTransport => {
+                expect_length(bytes, 4)?;
+                Attribute::RequestedTransport(bytes[0])
+            },
+            AttributeType::DontFragment => {
+                expect_length(bytes, 0)?;
+                Attribute::DontFragment
+            },
+            AttributeType::AccessToken => Attribute::AccessToken(bytes.to_vec()),
+            AttributeType::MessageIntegritySha256 => {
+                if bytes.len() < 16 || bytes.len() > 32 || bytes.len() % 4 != 0 {
+                    return Err(Error::InvalidLength { expected: 32, actual: bytes.len() });
+                }
+                Attribute::MessageIntegritySha256(bytes.to_vec())
+            },
+            AttributeType::PasswordAlgorithm => {
+                let (algorithm, size) = PasswordAlgorithm::from_bytes(bytes)?;
+                if bytes.len() > size + padding_len(size) {
+                    return Err(Error::InvalidLength { expected: size, actual: bytes.len() });
+                }
+                Attribute::PasswordAlgorithm(algorithm)
+            },
+            AttributeType::UserHash => {
+                expect_length(bytes, 32)?;
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(bytes);
+                Attribute::UserHash(hash)
+            },
+            AttributeType::TimerVal => {
+                expect_length(bytes, 4)?;
+                Attribute::TimerVal(read_u32(bytes))
+            },
+            AttributeType::ReservationToken => {
+                expect_length(bytes, 8)?;
+                let mut token = [0u8; 8];
+                token.copy_from_slice(bytes);
+                Attribute::ReservationToken(token)
+            },
+            AttributeType::Priority => {
+                expect_length(bytes, 4)?;
+                Attribute::Priority(read_u32(bytes))
+            },
+            AttributeType::UseCandidate => {
+                expect_length(bytes, 0)?;
+                Attribute::UseCandidate
+            },
+            AttributeType::Padding => Attribute::Padding(bytes.to_vec()),
+            AttributeType::ResponsePort => {
+                expect_length(bytes, 4)?;
+                Attribute::ResponsePort(read_u16(bytes))
+            },
+            AttributeType::ConnectionID => {
+                expect_length(bytes, 4)?;
+                Attribute::ConnectionID(read_u32(bytes))
+            },
+            AttributeType::PasswordAlgorithms => {
+                let mut algorithms: Vec<PasswordAlgorithm> = Vec::new();
+                let mut offset = 0;
+                while offset < bytes.len() {
+                    let (algorithm, size) = PasswordAlgorithm::from_bytes(&bytes[offset..])?;
+                    algorithms.push(algorithm);
+                    offset += size + padding_len(size);
+                }
+                Attribute::PasswordAlgorithms(algorithms)
+            },
+            AttributeType::Software        => Attribute::Software(read_string(bytes)?),
+            AttributeType::AlternateServer => Attribute::AlternateServer(decode_address(bytes)?),
+            AttributeType::TransactionTransmitCounter => {
+                expect_length(bytes, 4)?;
+                Attribute::TransactionTransmitCounter { request: bytes[2], response: bytes[3] }
+            },
+            AttributeType::CacheTimeout => {
+                expect_length(bytes, 4)?;
+                Attribute::CacheTimeout(read_u32(bytes))
+            },
+            AttributeType::FingerPrint => {
+                expect_length(bytes, 4)?;
+                Attribute::FingerPrint(read_u32(bytes))
+            },
+            AttributeType::ICEControlled => {
+                expect_length(bytes, 8)?;
+                Attribute::ICEControlled(read_u64(bytes))
+            },
+            AttributeType::ICEControlling => {
+                expect_length(bytes, 8)?;
+                Attribute::ICEControlling(read_u64(bytes))
+            },
+            AttributeType::ResponseOrigin => Attribute::ResponseOrigin(decode_address(bytes)?),
+            AttributeType::OtherAddress   => Attribute::OtherAddress(decode_address(bytes)?),
+            AttributeType::ECNCheckStun   => {
+                expect_length(bytes, 4)?;
+                let flags = read_u32(bytes);
+                Attribute::ECNCheckStun { ecf: ((flags >> 1) & 0x03) as u8, valid: flags & 0x01 != 0 }
+            },
+            AttributeType::ThirdPartyAuthorization => Attribute::ThirdPartyAuthorization(read_string(bytes)?),
+            AttributeType::MobilityTicket     => Attribute::MobilityTicket(bytes.to_vec()),
+            AttributeType::CiscoStunFlowData  => Attribute::CiscoStunFlowData(bytes.to_vec()),
+            AttributeType::ENFFlowDescription => Attribute::ENFFlowDescription(bytes.to_vec()),
+            AttributeType::ENFNetworkStatus   => Attribute::ENFNetworkStatus(bytes.to_vec())
+        };
+        Ok(attribute)
+    }
+    /// Check the length limits of the string attributes, enforced when encoding
+    /// only: a peer's over-long SOFTWARE or reason phrase is still decoded.
+    pub fn check_limits(&self) -> Result<(), Error> {
+        match *self {
+            Attribute::UserName(ref name) if name.len() > MAX_USERNAME_BYTES => {
+                Err(Error::TooLong { max: MAX_USERNAME_BYTES, actual: name.len() })
+            },
+            Attribute::Realm(ref s)
+            | Attribute::Nonce(ref s)
+            | Attribute::Software(ref s)
+            | Attribute::ErrorCode(_, ref s) => check_text(s),
+            _ => Ok(())
+        }
+    }
+    /// `None` for the attributes which are not registered in `AttributeType`.
+    pub fn attribute_type(&self) -> Option<AttributeType> {
+        let attr_type = match *self {
+            Attribute::MappedAddress(_)        => AttributeType::MappedAddress,
+            Attribute::ResponseAddress(_)      => AttributeType::ResponseAddress,
+            Attribute::ChangeRequest { .. }    => AttributeType::ChangeRequest,
+            Attribute::SourceAddress(_)        => AttributeType::SourceAddress,
+            Attribute::ChangedAddress(_)       => AttributeType::ChangedAddress,
+            Attribute::UserName(_)             => AttributeType::UserName,
+            Attribute::Password(_)             => AttributeType::Password,
+            Attribute::MessageIntegrity(_)     => AttributeType::MessageIntegrity,
+            Attribute::ErrorCode(_, _)         => AttributeType::ErrorCode,
+            Attribute::UnknownAttribute(_)     => AttributeType::UnknownAttribute,
+            Attribute::ReflectedFrom(_)        => AttributeType::ReflectedFrom,
+            Attribute::ChannelNumber(_)        => AttributeType::ChannelNumber,
+            Attribute::LifeTime(_)             => AttributeType::LifeTime,
+            Attribute::BandWidth(_)            => AttributeType::BandWidth,
+            Attribute::XorPeerAddress(_)       => AttributeType::XorPeerAddress,
+            Attribute::Data(_)                 => AttributeType::Data,
+            Attribute::Realm(_)                => AttributeType::Realm,
+            Attribute::Nonce(_)                => AttributeType::Nonce,
+            Attribute::XorRelayedAddress(_)    => AttributeType::XorRelayedAddress,
+            Attribute::RequestAddressFamily(_) => AttributeType::RequestAddressFamily,
+            Attribute::EvenPort(_)             => AttributeType::EvenPort,
+            Attribute::RequestedTransport(_)   => AttributeType::RequestedTransport,
+            Attribute::DontFragment            => AttributeType::DontFragment,
+            Attribute::AccessToken(_)          => AttributeType::AccessToken,
+            Attribute::MessageIntegritySha256(_) => AttributeType::MessageIntegritySha256,
+            Attribute::PasswordAlgorithm(_)    => AttributeType::PasswordAlgorithm,
+            Attribute::UserHash(_)             => AttributeType::UserHash,
+            Attribute::XorMappedAddress(_)     => AttributeType::XorMappedAddress,
+            Attribute::TimerVal(_)             => AttributeType::TimerVal,
+            Attribute::ReservationToken(_)     => AttributeType::ReservationToken,
+            Attribute::Priority(_)             => AttributeType::Priority,
+            Attribute::UseCandidate            => AttributeType::UseCandidate,
+            Attribute::Padding(_)              => AttributeType::Padding,
+            Attribute::ResponsePort(_)         => AttributeType::ResponsePort,
+            Attribute::ConnectionID(_)         => AttributeType::ConnectionID,
+            Attribute::PasswordAlgorithms(_)   => AttributeType::PasswordAlgorithms,
+            Attribute::Software(_)             => AttributeType::Software,
+            Attribute::AlternateServer(_)      => AttributeType::AlternateServer,
+            Attribute::TransactionTransmitCounter { .. } => AttributeType::TransactionTransmitCounter,
+            Attribute::CacheTimeout(_)         => AttributeType::CacheTimeout,
+            Attribute::FingerPrint(_)          => AttributeType::FingerPrint,
+            Attribute::ICEControlled(_)        => AttributeType::ICEControlled,
+            Attribute::ICEControlling(_)       => AttributeType::ICEControlling,
+            Attribute::ResponseOrigin(_)       => AttributeType::ResponseOrigin,
+            Attribute::OtherAddress(_)         => AttributeType::OtherAddress,
+            Attribute::ECNCheckStun { .. }     => AttributeType::ECNCheckStun,
+            Attribute::ThirdPartyAuthorization(_) => AttributeType::ThirdPartyAuthorization,
+            Attribute::MobilityTicket(_)       => AttributeType::MobilityTicket,
+            Attribute::CiscoStunFlowData(_)    => AttributeType::CiscoStunFlowData,
+            Attribute::ENFFlowDescription(_)   => AttributeType::ENFFlowDescription,
+            Attribute::ENFNetworkStatus(_)     => AttributeType::ENFNetworkStatus,
+            Attribute::Unknown { .. }          => return None
+        };
+        Some(attr_type)
+    }
+    /// The 16 bits attribute type written on the wire.
+    pub fn typ(&self) -> u16 {
+        match *self {
+            Attribute::Unknown { typ, .. } => typ,
+            _ => self.attribute_type().unwrap().to_u32() as u16
+        }
+    }
+    pub fn is_comprehension_required(&self) -> bool {
+        is_comprehension_required(self.typ())
+    }
+    fn write_value<W: Write>(&self, header: &Header, writer: &mut W) -> io::Result<()> {
+        match *self {
+            Attribute::MappedAddress(ref socket_addr)
+            | Attribute::ResponseAddress(ref socket_addr)
+            | Attribute::SourceAddress(ref socket_addr)
+            | Attribute::ChangedAddress(ref socket_addr)
+            | Attribute::ReflectedFrom(ref socket_addr)
+            | Attribute::AlternateServer(ref socket_addr)
+            | Attribute::ResponseOrigin(ref socket_addr)
+            | Attribute::OtherAddress(ref socket_addr) => Address::from(*socket_addr).write_to(writer),
+            Attribute::ChangeRequest { change_ip, change_port } => {
+                let mut flags = 0u8;
+                if change_ip   { flags |= 0x04; }
+                if change_port { flags |= 0x02; }
+                writer.write_all(&[0, 0, 0, flags])
+            },
+            Attribute::UserName(ref s)
+            | Attribute::Password(ref s)
+            | Attribute::Realm(ref s)
+            | Attribute::Nonce(ref s)
+            | Attribute::Software(ref s)
+            | Attribute::ThirdPartyAuthorization(ref s) => writer.write_all(s.as_bytes()),
+            Attribute::MessageIntegrity(ref hmac) => writer.write_all(hmac),
+            Attribute::MessageIntegritySha256(ref hmac) => writer.write_all(hmac),
+            Attribute::PasswordAlgorithm(ref algorithm) => algorithm.write_to(writer),
+            Attribute::PasswordAlgorithms(ref algorithms) => {
+                for algorithm in algorithms.iter() {
+                    algorithm.write_to(writer)?;
+                    writer.write_all(&[0u8; 3][..padding_len(algorithm.parameters().len())])?;
+                }
+                Ok(())
+            },
+            Attribute::UserHash(ref hash) => writer.write_all(hash),
+            Attribute::ErrorCode(ref error_code, ref reason) => {
+                writer.write_all(&[0, 0, error_code.class() & 0x07, error_code.number()])?;
+                writer.write_all(reason.as_bytes())
+            },
+            Attribute::UnknownAttribute(ref types) => {
+                for typ in types.iter() {
+                    writer.write_all(&typ.to_be_bytes())?;
+                }
+                // RFC 3489 11.2.9: an odd number of attributes is padded by
+                // repeating one of them instead of zeroes.
+                if header.is_classic() && types.len() % 2 == 1 {
+                    writer.write_all(&types[types.len() - 1].to_be_bytes())?;
+                }
+                Ok(())
+            },
+            Attribute::ChannelNumber(number) => {
+                writer.write_all(&number.to_be_bytes())?;
+                writer.write_all(&[0, 0])
+            },
+            Attribute::LifeTime(n)
+            | Attribute::BandWidth(n)
+            | Attribute::TimerVal(n)
+            | Attribute::Priority(n)
+            | Attribute::ConnectionID(n)
+            | Attribute::CacheTimeout(n)
+            | Attribute::FingerPrint(n) => writer.write_all(&n.to_be_bytes()),
+            Attribute::XorPeerAddress(ref socket_addr)
+            | Attribute::XorRelayedAddress(ref socket_addr)
+            | Attribute::XorMappedAddress(ref socket_addr) => {
+                Address::from(xor_address(socket_addr, header)).write_to(writer)
+            },
+            Attribute::Data(ref bytes)
+            | Attribute::AccessToken(ref bytes)
+            | Attribute::Padding(ref bytes)
+            | Attribute::MobilityTicket(ref bytes)
+            | Attribute::CiscoStunFlowData(ref bytes)
+            | Attribute::ENFFlowDescription(ref bytes)
+            | Attribute::ENFNetworkStatus(ref bytes)
+            | Attribute::Unknown { value: ref bytes, .. } => writer.write_all(bytes),
+            Attribute::RequestAddressFamily(ref family) => writer.write_all(&[family.to_u32() as u8, 0, 0, 0]),
+            Attribute::EvenPort(reserve) => writer.write_all(&[if reserve { 0x80 } else { 0x00 }]),
+            Attribute::RequestedTransport(protocol) => writer.write_all(&[protocol, 0, 0, 0]),
+            Attribute::DontFragment
+            | Attribute::UseCandidate => Ok(()),
+            Attribute::ReservationToken(ref token) => writer.write_all(token),
+            Attribute::ResponsePort(port) => {
+                writer.write_all(&port.to_be_bytes())?;
+                writer.write_all(&[0, 0])
+            },
+            Attribute::TransactionTransmitCounter { request, response } => writer.write_all(&[0, 0, request, response]),
+            Attribute::ICEControlled(n)
+            | Attribute::ICEControlling(n) => writer.write_all(&n.to_be_bytes()),
+            Attribute::ECNCheckStun { ecf, valid } => {
+                let flags = ((ecf & 0x03) << 1) | if valid { 0x01 } else { 0x00 };
+                writer.write_all(&[0, 0, 0, flags])
+            }
         }
     }
-}
\ No newline at end of file
+    /// The size of the attribute value, without padding.
+    fn value_len(&self, header: &Header) -> usize {
+        let mut counter = Counter(0);
+        self.write_value(header, &mut counter).unwrap();
+        counter.0
+    }
+    /// The size of the encoded attribute: type, length, value and padding.
+    pub fn encoded_len(&self, header: &Header) -> usize {
+        let length = self.value_len(header);
+        4 + length + padding_len(length)
+    }
+    pub fn into_bytes(&self, header: &Header) -> Result<Vec<u8>, Error> {
+        self.into_bytes_with_padding(header, 0)
+    }
+    /// Encode the attribute, the value is padded to a multiple of 4 bytes with `padding`.
+    pub fn into_bytes_with_padding(&self, header: &Header, padding: u8) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0u8; self.encoded_len(header)];
+        self.encode(header, padding, &mut bytes)?;
+        Ok(bytes)
+    }
+    /// Encode the attribute at the start of `buffer` and return its size,
+    /// the value is padded to a multiple of 4 bytes with `padding`.
+    pub fn encode(&self, header: &Header, padding: u8, buffer: &mut [u8]) -> Result<usize, Error> {
+        /*
+            type  : AttributeType,  // 16 bits
+            length: u32,            // 16 bits
+            value : Attribute       // 32 bits ( Or More. )
+        */
+        self.check_limits()?;
+        let length = self.value_len(header);
+        if length > u16::MAX as usize {
+            return Err(Error::MessageTooLarge { max: u16::MAX as usize, actual: length });
+        }
+        let size = 4 + length + padding_len(length);
+        if buffer.len() < size {
+            return Err(Error::BufferTooSmall { capacity: buffer.len(), needed: size });
+        }
+        buffer[0..2].copy_from_slice(&self.typ().to_be_bytes());
+        buffer[2..4].copy_from_slice(&(length as u16).to_be_bytes());
+        self.write_value(header, &mut &mut buffer[4..4 + length])?;
+        for b in &mut buffer[4 + length..size] {
+            *b = padding;
+        }
+        Ok(size)
+    }
+}
+
+/// An ERROR-CODE attribute with the default reason phrase of `error_code`.
+impl From<ErrorCode> for Attribute {
+    fn from(error_code: ErrorCode) -> Self {
+        let reason = error_code.to_string();
+        Attribute::ErrorCode(error_code, reason)
+    }
+}
@@ -0,0 +1,132 @@
+use super::{Header, Class, Method, TransactionId, Attribute, AttributeType, HEADER_SIZE, FINGERPRINT_SIZE};
+use super::{integrity, fingerprint};
+use super::super::error::Error;
+
+/**
+Build a STUN message in place, into a buffer supplied by the caller.
+
+The header is written first, every attribute is appended right after the
+previous one and the message length is kept up to date, `finish` (or one
+of the `finish_with_*` variants) appends MESSAGE-INTEGRITY and FINGERPRINT
+when asked and returns the size of the message, e.g.:
+    let mut buffer = [0u8; 548];
+    let size = MessageBuilder::response(&mut buffer, request.header(), Class::SuccessResponse)?
+        .attribute(&Attribute::XorMappedAddress(peer))?
+        .fingerprint(true)
+        .finish_with_integrity(&key)?;
+**/
+#[derive(Debug)]
+pub struct MessageBuilder<'a> {
+    header     : Header,
+    buffer     : &'a mut [u8],
+    offset     : usize,
+    fingerprint: bool
+}
+
+impl<'a> MessageBuilder<'a> {
+    pub fn new(buffer: &'a mut [u8], class: Class, method: Method, transaction_id: TransactionId) -> Result<Self, Error> {
+        MessageBuilder::with_header(buffer, Header::new(class, method, transaction_id))
+    }
+    /// A response (or another message of class `class`) to `request`: same
+    /// method, magic cookie and transaction id.
+    pub fn response(buffer: &'a mut [u8], request: &Header, class: Class) -> Result<Self, Error> {
+        let mut header = request.clone();
+        header.set_class(class);
+        MessageBuilder::with_header(buffer, header)
+    }
+    /// A message with the given header (its length is ignored), e.g. a RFC 3489 one.
+    pub fn with_header(buffer: &'a mut [u8], header: Header) -> Result<Self, Error> {
+        if buffer.len() < HEADER_SIZE {
+            return Err(Error::BufferTooSmall { capacity: buffer.len(), needed: HEADER_SIZE });
+        }
+        let mut header = header;
+        header.set_length(0);
+        buffer[..HEADER_SIZE].copy_from_slice(&header.into_bytes());
+        Ok(MessageBuilder {
+            header,
+            buffer,
+            offset     : HEADER_SIZE,
+            fingerprint: false
+        })
+    }
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    /// Append `attribute` (encoded against this message's header, which the XOR
+    /// address attributes need).
+    pub fn attribute(self, attribute: &Attribute) -> Result<Self, Error> {
+        let size = attribute.encoded_len(&self.header);
+        let mut builder = self.reserve(size)?;
+        let offset = builder.offset;
+        attribute.encode(&builder.header, 0, &mut builder.buffer[offset..offset + size])?;
+        builder.advance(size);
+        Ok(builder)
+    }
+    /// Append an attribute from its type and already encoded value.
+    pub fn raw_attribute(self, typ: u16, value: &[u8]) -> Result<Self, Error> {
+        let padding = super::padding_len(value.len());
+        let mut builder = self.reserve(4 + value.len() + padding)?;
+        let offset = builder.offset;
+        builder.buffer[offset..offset + 2].copy_from_slice(&typ.to_be_bytes());
+        builder.buffer[offset + 2..offset + 4].copy_from_slice(&(value.len() as u16).to_be_bytes());
+        builder.buffer[offset + 4..offset + 4 + value.len()].copy_from_slice(value);
+        for b in &mut builder.buffer[offset + 4 + value.len()..offset + 4 + value.len() + padding] {
+            *b = 0;
+        }
+        builder.advance(4 + value.len() + padding);
+        Ok(builder)
+    }
+    /// Whether the finished message ends with a FINGERPRINT attribute.
+    pub fn fingerprint(mut self, fingerprint: bool) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+    pub fn finish(self) -> Result<usize, Error> {
+        self.finish_fingerprint()
+    }
+    /// Append MESSAGE-INTEGRITY computed with `key`, then FINGERPRINT if asked.
+    pub fn finish_with_integrity(self, key: &[u8]) -> Result<usize, Error> {
+        let mut builder = self.reserve(4 + integrity::MESSAGE_INTEGRITY_SIZE)?;
+        builder.set_length(builder.offset + 4 + integrity::MESSAGE_INTEGRITY_SIZE);
+        let hmac = integrity::hmac_sha1(key, &builder.buffer[..builder.offset]);
+        builder.raw_attribute(AttributeType::MessageIntegrity.to_u32() as u16, &hmac)?.finish_fingerprint()
+    }
+    /// Append MESSAGE-INTEGRITY-SHA256 computed with `key`, then FINGERPRINT if asked.
+    pub fn finish_with_integrity_sha256(self, key: &[u8]) -> Result<usize, Error> {
+        let mut builder = self.reserve(4 + integrity::MESSAGE_INTEGRITY_SHA256_SIZE)?;
+        builder.set_length(builder.offset + 4 + integrity::MESSAGE_INTEGRITY_SHA256_SIZE);
+        let hmac = integrity::hmac_sha256(key, &builder.buffer[..builder.offset]);
+        builder.raw_attribute(AttributeType::MessageIntegritySha256.to_u32() as u16, &hmac)?.finish_fingerprint()
+    }
+    fn finish_fingerprint(self) -> Result<usize, Error> {
+        if !self.fingerprint {
+            return Ok(self.offset);
+        }
+        let mut builder = self.reserve(FINGERPRINT_SIZE)?;
+        builder.set_length(builder.offset + FINGERPRINT_SIZE);
+        let crc = fingerprint::fingerprint(&builder.buffer[..builder.offset]);
+        let builder = builder.raw_attribute(AttributeType::FingerPrint.to_u32() as u16, &crc.to_be_bytes())?;
+        Ok(builder.offset)
+    }
+    fn reserve(self, size: usize) -> Result<Self, Error> {
+        let needed = self.offset + size;
+        if needed > self.buffer.len() {
+            return Err(Error::BufferTooSmall { capacity: self.buffer.len(), needed });
+        }
+        if needed - HEADER_SIZE > u16::MAX as usize {
+            return Err(Error::MessageTooLarge { max: u16::MAX as usize, actual: needed - HEADER_SIZE });
+        }
+        Ok(self)
+    }
+    fn advance(&mut self, size: usize) {
+        self.offset += size;
+        let offset = self.offset;
+        self.set_length(offset);
+    }
+    /// Write the message length of a message ending at `end` in the header.
+    fn set_length(&mut self, end: usize) {
+        let length = (end - HEADER_SIZE) as u16;
+        self.header.set_length(length);
+        self.buffer[2..4].copy_from_slice(&length.to_be_bytes());
+    }
+}
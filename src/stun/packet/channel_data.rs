@@ -0,0 +1,87 @@
+use super::padding_len;
+use super::super::error::Error;
+
+pub const CHANNEL_DATA_HEADER_SIZE: usize = 4;
+pub const MIN_CHANNEL_NUMBER: u16 = 0x4000;
+pub const MAX_CHANNEL_NUMBER: u16 = 0x7FFF;
+
+/**
+0                   1                   2                   3
+0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|         Channel Number        |            Length             |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                                                               |
+/                       Application Data                        /
+/                                                               /
+|                                                               |
+|                               +-------------------------------+
+|                               |
++-------------------------------+
+
+Figure 5: Format of ChannelData Message
+
+Refs:
+    https://tools.ietf.org/html/rfc5766#section-11.4
+
+The channel number is in the range 0x4000 through 0x7FFF and the length
+counts the application data only. Over TCP the message is padded to a
+multiple of 4 bytes, over UDP the padding is optional, so any bytes after
+the application data are ignored when decoding.
+**/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelData<'a> {
+    channel: u16,
+    data   : &'a [u8]
+}
+
+impl<'a> ChannelData<'a> {
+    pub fn new(channel: u16, data: &'a [u8]) -> Result<Self, Error> {
+        if !(MIN_CHANNEL_NUMBER..=MAX_CHANNEL_NUMBER).contains(&channel) {
+            return Err(Error::InvalidChannelNumber(channel as u32));
+        }
+        if data.len() > u16::MAX as usize {
+            return Err(Error::TooLong { max: u16::MAX as usize, actual: data.len() });
+        }
+        Ok(ChannelData { channel, data })
+    }
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < CHANNEL_DATA_HEADER_SIZE {
+            return Err(Error::Truncated { offset: bytes.len(), needed: CHANNEL_DATA_HEADER_SIZE - bytes.len() });
+        }
+        let channel = ((bytes[0] as u16) << 8) | bytes[1] as u16;
+        let length  = ((bytes[2] as usize) << 8) | bytes[3] as usize;
+        if bytes.len() < CHANNEL_DATA_HEADER_SIZE + length {
+            return Err(Error::Truncated {
+                offset: bytes.len(),
+                needed: CHANNEL_DATA_HEADER_SIZE + length - bytes.len()
+            });
+        }
+        ChannelData::new(channel, &bytes[CHANNEL_DATA_HEADER_SIZE..CHANNEL_DATA_HEADER_SIZE + length])
+    }
+    pub fn channel(&self) -> u16 {
+        self.channel
+    }
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+    /// The 4 bytes header, to send in front of the data without copying it.
+    pub fn header_bytes(&self) -> [u8; CHANNEL_DATA_HEADER_SIZE] {
+        let channel = self.channel.to_be_bytes();
+        let length  = (self.data.len() as u16).to_be_bytes();
+        [channel[0], channel[1], length[0], length[1]]
+    }
+    /// Encode the message without padding (UDP).
+    pub fn into_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(CHANNEL_DATA_HEADER_SIZE + self.data.len());
+        bytes.extend(&self.header_bytes());
+        bytes.extend(self.data);
+        bytes
+    }
+    /// Encode the message padded to a multiple of 4 bytes (TCP).
+    pub fn into_bytes_padded(&self) -> Vec<u8> {
+        let mut bytes = self.into_bytes();
+        bytes.extend(vec![0u8; padding_len(self.data.len())]);
+        bytes
+    }
+}
@@ -1,7 +1,10 @@
 
 use std::str::FromStr;
+use std::fmt;
 use std::string::ToString;
 
+use super::super::error::Error;
+
 /**
 
 Refs:
@@ -13,6 +16,7 @@ Error Code:
     range 0 - 699
 
 0-299   Reserved
+700-    Invalid (the class is 3 bits, from 3 to 6)
 301-399 Unassigned
 402     Unassigned
 406-419 Unassigned  
@@ -46,7 +50,7 @@ Error Code:
 500     Server Error    [RFC5389]
 508     Insufficient Capacity   [RFC5766]
 **/
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ErrorCode {
     TryAlternate,  // 300
     BadRequest,    // 400
@@ -67,53 +71,55 @@ pub enum ErrorCode {
     RoleConflict,                 // 487
     ServerError,                  // 500
     InsufficientCapacity,         // 508
-    GlobalFailure                 // 600 (IANA 遗漏定义: https://www.ietf.org/rfc/rfc3489.txt)
+    GlobalFailure,                // 600 (IANA 遗漏定义: https://www.ietf.org/rfc/rfc3489.txt)
+    Other(u16, String)            // unassigned code (300-699) and its reason phrase
 }
 
-impl ToString for ErrorCode {
-    fn to_string (&self) -> String {
-        match *self {
-            ErrorCode::TryAlternate                 => "Try Alternate".to_owned(),
-            ErrorCode::BadRequest                   => "Bad request".to_owned(),
-            ErrorCode::Unauthorized                 => "Unauthorized".to_owned(),
-            ErrorCode::Forbidden                    => "Forbidden".to_owned(),
-            ErrorCode::MobilityForbidden            => "Mobility forbidden".to_owned(),
-            ErrorCode::UnknownAttribute             => "Unknown attribute(s)".to_owned(),
-            ErrorCode::IntegrityCheckFailure        => "Integrity Check Failure".to_owned(),
-            ErrorCode::AllocationMismatch           => "Allocation mismatch".to_owned(),
-            ErrorCode::StaleNonce                   => "Stale nonce".to_owned(),
-            ErrorCode::AddressFamilyNotSupported    => "Address family not supported".to_owned(),
-            ErrorCode::WrongCredentials             => "Wrong credentials".to_owned(),
-            ErrorCode::UnsupportedTransportProtocol => "Unsupported transport protocol".to_owned(),
-            ErrorCode::PeerAddressFamilyMismatch    => "Peer address family mismatch".to_owned(),
-            ErrorCode::ConnectionAlreadyExists      => "Connection Already Exists".to_owned(),
-            ErrorCode::ConnectionTimeoutOrFailure   => "Connection Timeout or Failure".to_owned(),
-            ErrorCode::AllocationQuotaReached       => "Allocation quota reached".to_owned(),
-            ErrorCode::RoleConflict                 => "Role conflict".to_owned(),
-            ErrorCode::ServerError                  => "Server error".to_owned(),
-            ErrorCode::InsufficientCapacity         => "Insufficient capacity".to_owned(),
-            ErrorCode::GlobalFailure                => "Global Failure".to_owned(),
-        }
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ErrorCode::TryAlternate                 => "Try Alternate",
+            ErrorCode::BadRequest                   => "Bad request",
+            ErrorCode::Unauthorized                 => "Unauthorized",
+            ErrorCode::Forbidden                    => "Forbidden",
+            ErrorCode::MobilityForbidden            => "Mobility forbidden",
+            ErrorCode::UnknownAttribute             => "Unknown attribute(s)",
+            ErrorCode::IntegrityCheckFailure        => "Integrity Check Failure",
+            ErrorCode::AllocationMismatch           => "Allocation mismatch",
+            ErrorCode::StaleNonce                   => "Stale nonce",
+            ErrorCode::AddressFamilyNotSupported    => "Address family not supported",
+            ErrorCode::WrongCredentials             => "Wrong credentials",
+            ErrorCode::UnsupportedTransportProtocol => "Unsupported transport protocol",
+            ErrorCode::PeerAddressFamilyMismatch    => "Peer address family mismatch",
+            ErrorCode::ConnectionAlreadyExists      => "Connection Already Exists",
+            ErrorCode::ConnectionTimeoutOrFailure   => "Connection Timeout or Failure",
+            ErrorCode::AllocationQuotaReached       => "Allocation quota reached",
+            ErrorCode::RoleConflict                 => "Role conflict",
+            ErrorCode::ServerError                  => "Server error",
+            ErrorCode::InsufficientCapacity         => "Insufficient capacity",
+            ErrorCode::GlobalFailure                => "Global Failure",
+            ErrorCode::Other(_, ref reason)         => reason.as_str()
+        })
     }
 }
 
 impl ErrorCode {
-    pub fn from_u32(n: u32) -> Result<Self, &'static str> {
+    pub fn from_u32(n: u32) -> Result<Self, Error> {
         match n {
-            0 ... 299    => Err("Reserved"),
-            301 ... 399
+            0 ..= 299    => Err(Error::InvalidErrorCode(n)),
+            301 ..= 399
             | 402
-            | 406 ... 419
+            | 406 ..= 419
             | 404
             | 439
-            | 444 ... 445
-            | 448 ... 485
-            | 488 ... 499
-            | 501 ... 507
-            | 421 ... 430
-            | 432 ... 436
-            | 509 ... 599
-            | 601 ... 699 => Err("Unassigned"),
+            | 444 ..= 445
+            | 448 ..= 485
+            | 488 ..= 499
+            | 501 ..= 507
+            | 421 ..= 430
+            | 432 ..= 436
+            | 509 ..= 599
+            | 601 ..= 699 => Err(Error::InvalidErrorCode(n)),
             300 => Ok(ErrorCode::TryAlternate),
             400 => Ok(ErrorCode::BadRequest),
             401 => Ok(ErrorCode::Unauthorized),
@@ -134,7 +140,7 @@ impl ErrorCode {
             500 => Ok(ErrorCode::ServerError),
             508 => Ok(ErrorCode::InsufficientCapacity),
             600 => Ok(ErrorCode::GlobalFailure),
-            _   => Err("Code Range(0 ... 699)")
+            _   => Err(Error::InvalidErrorCode(n))
         }
     }
     pub fn to_u32 (&self) -> u32 {
@@ -158,9 +164,28 @@ impl ErrorCode {
             ErrorCode::RoleConflict                 => 487,
             ErrorCode::ServerError                  => 500,
             ErrorCode::InsufficientCapacity         => 508,
-            ErrorCode::GlobalFailure                => 600
+            ErrorCode::GlobalFailure                => 600,
+            ErrorCode::Other(code, _)               => code as u32
+        }
+    }
+    /// The code of an ERROR-CODE attribute, a code without a variant of its
+    /// own is kept as `ErrorCode::Other` with `reason`. The class must be
+    /// between 3 and 6 and the number between 0 and 99.
+    pub fn from_code(code: u32, reason: &str) -> Result<Self, Error> {
+        if !(300..=699).contains(&code) {
+            return Err(Error::InvalidErrorCode(code));
+        }
+        match ErrorCode::from_u32(code) {
+            Ok(error_code) => Ok(error_code),
+            Err(_)         => Ok(ErrorCode::Other(code as u16, reason.to_owned()))
         }
     }
+    pub fn class(&self) -> u8 {
+        (self.to_u32() / 100) as u8
+    }
+    pub fn number(&self) -> u8 {
+        (self.to_u32() % 100) as u8
+    }
     pub fn to_bytes(&self) -> Vec<u8> {
         self.to_string().into_bytes()
     }
@@ -0,0 +1,35 @@
+use crc32fast;
+
+use super::super::constant::STUN_FINGERPRINT_XOR_VALUE;
+use super::{HEADER_SIZE, FINGERPRINT_SIZE};
+
+/**
+Refs:
+    https://tools.ietf.org/html/rfc5389#section-15.5
+
+The FINGERPRINT attribute is the CRC-32 of the STUN message up to (but
+excluding) the FINGERPRINT attribute itself, XOR'ed with 0x5354554E.
+The length field of the header includes the FINGERPRINT attribute, which
+is always the last attribute of the message.
+
+It helps to tell STUN packets apart from other protocols (RTP, DTLS ...)
+multiplexed on the same port.
+**/
+pub fn fingerprint(message: &[u8]) -> u32 {
+    crc32fast::hash(message) ^ STUN_FINGERPRINT_XOR_VALUE
+}
+
+/// Check the FINGERPRINT of a complete encoded message, `false` when the
+/// message does not end with a valid FINGERPRINT attribute.
+pub fn check_fingerprint(bytes: &[u8]) -> bool {
+    if bytes.len() < HEADER_SIZE + FINGERPRINT_SIZE {
+        return false;
+    }
+    let offset = bytes.len() - FINGERPRINT_SIZE;
+    if bytes[offset..offset + 4] != [0x80, 0x28, 0x00, 0x04] {
+        return false;
+    }
+    let crc = ((bytes[offset + 4] as u32) << 24) | ((bytes[offset + 5] as u32) << 16)
+            | ((bytes[offset + 6] as u32) << 8) | bytes[offset + 7] as u32;
+    crc == fingerprint(&bytes[..offset])
+}
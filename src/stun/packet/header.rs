@@ -1,11 +1,18 @@
 
 use std::str::FromStr;
+use std::fmt;
 use std::string::ToString;
 
 use super::super::constant::STUN_MAGIC_COOKIE;
+use super::super::error::Error;
+use super::transaction_id::TransactionId;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 /// Message Class
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Class {
     Request,
     Indication,
@@ -13,25 +20,25 @@ pub enum Class {
     FailureResponse
 }
 
-impl ToString for Class {
-    fn to_string(&self) -> String {
-        match *self {
-            Class::Request            => "Request".to_owned(),
-            Class::Indication         => "Indication".to_owned(),
-            Class::SuccessResponse    => "Success Response".to_owned(),
-            Class::FailureResponse    => "Failure Response".to_owned()
-        }
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Class::Request            => "Request",
+            Class::Indication         => "Indication",
+            Class::SuccessResponse    => "Success Response",
+            Class::FailureResponse    => "Failure Response"
+        })
     }
 }
 
 impl Class {
-    pub fn from_u32(n: u32) -> Result<Self, &'static str> {
+    pub fn from_u32(n: u32) -> Result<Self, Error> {
         match n {
             0b00 => Ok(Class::Request),
             0b01 => Ok(Class::Indication),
             0b10 => Ok(Class::SuccessResponse),
             0b11 => Ok(Class::FailureResponse),
-            _    => Err("range(0b00 ... 0b11)")
+            _    => Err(Error::InvalidClass(n))
         }
     }
     pub fn to_u32(&self) -> u32 {
@@ -67,7 +74,8 @@ Range:
 
 **/
 /// Message Method
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Method {
     Binding,
     SharedSecret,
@@ -82,32 +90,32 @@ pub enum Method {
     ConnectionAttempt
 }
 
-impl ToString for Method {
-    fn to_string(&self) -> String {
-        match *self {
-            Method::Binding       => "Binding".to_owned(),
-            Method::SharedSecret  => "SharedSecret".to_owned(),
-            Method::Allocate      => "Allocate".to_owned(),
-            Method::Refresh       => "Refresh".to_owned(),
-            Method::Send          => "Send".to_owned(),
-            Method::Data          => "Data".to_owned(),
-            Method::CreatePermission  => "CreatePermission".to_owned(),
-            Method::ChannelBind       => "ChannelBind".to_owned(),
-            Method::Connect           => "Connect".to_owned(),
-            Method::ConnectionBind    => "ConnectionBind".to_owned(),
-            Method::ConnectionAttempt => "ConnectionAttempt".to_owned()
-        }
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Method::Binding       => "Binding",
+            Method::SharedSecret  => "SharedSecret",
+            Method::Allocate      => "Allocate",
+            Method::Refresh       => "Refresh",
+            Method::Send          => "Send",
+            Method::Data          => "Data",
+            Method::CreatePermission  => "CreatePermission",
+            Method::ChannelBind       => "ChannelBind",
+            Method::Connect           => "Connect",
+            Method::ConnectionBind    => "ConnectionBind",
+            Method::ConnectionAttempt => "ConnectionAttempt"
+        })
     }
 }
 
 impl Method {
-    pub fn from_u32(n: u32) -> Result<Self, &'static str> {
+    pub fn from_u32(n: u32) -> Result<Self, Error> {
         match n {
-            0x000                   => Err("Reserved"),
-            0x100 ... 0xFFF         => Err("Reserved(For DTLS-SRTP multiplexing collision avoidance, \
-                                            see [RFC7983]. Cannot be made available for assignment \
-                                            without IETF Review.)"),
-            0x005 | 0x00D ... 0x0FF => Err("Unassigned"),
+            0x000                   => Err(Error::ReservedMethod(n)),
+            // Reserved(For DTLS-SRTP multiplexing collision avoidance, see [RFC7983].
+            // Cannot be made available for assignment without IETF Review.)
+            0x100 ..= 0xFFF         => Err(Error::ReservedMethod(n)),
+            0x005 | 0x00D ..= 0x0FF => Err(Error::UnknownMethod(n)),
             
             0x001 => Ok(Method::Binding),
             0x002 => Ok(Method::SharedSecret),
@@ -120,7 +128,7 @@ impl Method {
             0x00A => Ok(Method::Connect),
             0x00B => Ok(Method::ConnectionBind),
             0x00C => Ok(Method::ConnectionAttempt),
-            _     => Err("Range(0x000 ... 0xFFF)")
+            _     => Err(Error::UnknownMethod(n))
         }
     }
     pub fn to_u32(&self) -> u32 {
@@ -156,83 +164,129 @@ impl Method {
 |                                                               |
 +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-          Figure 2: Format of STUN Message Header
+Figure 2: Format of STUN Message Header
 
 Head Struct
     [0, 1, 0, 0, 33, 18, 164, 66, 70, 149, 250, 122, 253, 177, 191, 174, 164, 118, 181, 61]
-    Magic Code     : 2  bits
+    Zero Bits      : 2  bits
     Message Type   : 14 bits ( class: 2 bits; method: 12 bits; )
     Message Length : 16 bits (  2 Bytes )
     Magic Cookie   : 32 bits (  4 Bytes ) 
     Transaction ID : 96 bits ( 12 Bytes )
 
 **/
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Header {
-    magic_code    : u8,     //  2 bits
     class         : Class,  //  2 bits
     method        : Method, // 12 bits
     length        : u16,    // 16 bits message attribute length
     magic_cookie  : u32,    // 32 bits (Must Be 0x2112A442 (554869826))
-    transaction_id: String  // 96 bits unique
+    transaction_id: TransactionId  // 96 bits unique
 }
 
 pub fn bytes_to_hex_str(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join("")
 }
 
+/**
+ 0                 1
+ 2  3  4 5 6 7 8 9 0 1 2 3 4 5
++--+--+-+-+-+-+-+-+-+-+-+-+-+-+
+|M |M |M|M|M|C|M|M|M|C|M|M|M|M|
+|11|10|9|8|7|1|6|5|4|0|3|2|1|0|
++--+--+-+-+-+-+-+-+-+-+-+-+-+-+
+
+Figure 3: Format of STUN Message Type Field
+
+The message type interleaves the class bits C1 and C0 with the method
+bits M11 through M0.
+**/
+pub fn message_type(class: &Class, method: &Method) -> u16 {
+    let c = class.to_u32() as u16;
+    let m = method.to_u32() as u16;
+    (m & 0x000F) | ((m & 0x0070) << 1) | ((m & 0x0F80) << 2) | ((c & 0b01) << 4) | ((c & 0b10) << 7)
+}
+
+/// Split a STUN Message Type into its raw class and method numbers.
+pub fn split_message_type(message_type: u16) -> (u32, u32) {
+    let c = ((message_type >> 4) & 0b01) | ((message_type >> 7) & 0b10);
+    let m = (message_type & 0x000F) | ((message_type >> 1) & 0x0070) | ((message_type >> 2) & 0x0F80);
+    (c as u32, m as u32)
+}
+
 impl Header {
-    pub fn from_bytes (bytes: &[u8]) -> Result<Self, &'static str> {
+    pub fn new(class: Class, method: Method, transaction_id: TransactionId) -> Self {
+        Header {
+            class,
+            method,
+            length        : 0,
+            magic_cookie  : STUN_MAGIC_COOKIE,
+            transaction_id
+        }
+    }
+    /// A RFC 3489 header, the first 4 bytes of the 128-bit transaction id take
+    /// the place of the magic cookie.
+    pub fn new_classic(class: Class, method: Method, transaction_id: [u8; 16]) -> Self {
+        let mut id = [0u8; 12];
+        id.copy_from_slice(&transaction_id[4..]);
+        Header {
+            class,
+            method,
+            length        : 0,
+            magic_cookie  : ((transaction_id[0] as u32) << 24) | ((transaction_id[1] as u32) << 16)
+                          | ((transaction_id[2] as u32) << 8)  | transaction_id[3] as u32,
+            transaction_id: TransactionId::new(id)
+        }
+    }
+    pub fn from_bytes (bytes: &[u8]) -> Result<Self, Error> {
         if bytes.len() < 20 {
-            return Err("header size must be 20 Bytes.");
+            return Err(Error::Truncated { offset: bytes.len(), needed: 20 - bytes.len() });
+        }
+        // https://tools.ietf.org/html/rfc5389#section-6
+        // The most significant 2 bits of every STUN message MUST be zeroes.
+        let message_type = ((bytes[0] as u16) << 8) | bytes[1] as u16;
+        if message_type & 0xC000 != 0 {
+            return Err(Error::InvalidMessageType(message_type as u32));
         }
-        let bytes = &bytes[..20];
-        // https://tools.ietf.org/html/rfc5389#appendix-A
-        // 0b 00 01 000000000000
-        let bits = format!("{:08b}", bytes[0]) + format!("{:08b}", bytes[1]).as_ref();
-        let magic_code     = match u8::from_str_radix(&bits[0..2], 2) {
-            Ok(magic_code) => magic_code,
-            Err(_)         => return Err("magic code parse error")
-        };
-        let message_class  = match u8::from_str_radix(&bits[2..4], 2) {
-            Ok(message_class) => match Class::from_u32(message_class as u32) {
-                Ok(class) => class,
-                Err(e)    => return Err(e)
-            },
-            Err(_)        => return Err("message class error.")
-        };
-        let message_method  = match u16::from_str_radix(&bits[4..16], 2) {
-            Ok(message_method) => match Method::from_u32(message_method as u32) {
-                Ok(method) => method,
-                Err(e)     => return Err(e)
-            },
-            Err(_)         => return Err("message class error.")
-        };
+        let (class, method) = split_message_type(message_type);
 
-        let message_length = match u16::from_str_radix(bytes_to_hex_str(&bytes[2..4]).as_ref(), 16) {
-            Ok(message_length) => message_length,
-            Err(_)             => return Err("message length error")
-        };
-        let magic_cookie   = match u32::from_str_radix(bytes_to_hex_str(&bytes[4..8]).as_ref(), 16) {
-            Ok(magic_cookie) => magic_cookie,
-            Err(_)           => return Err("magic cookie error")
-        };
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&bytes[8..20]);
 
-        let transaction_id = if magic_cookie != STUN_MAGIC_COOKIE {
-            bytes_to_hex_str(&bytes[4..20])
-        } else {
-            bytes_to_hex_str(&bytes[8..20])
-        };
-        
+        // RFC 3489 messages keep the first 32 bits of their 128-bit
+        // transaction id in `magic_cookie`.
         Ok(Header{
-            magic_code    : magic_code,
-            class         : message_class,
-            method        : message_method,
-            length        : message_length,
-            magic_cookie  : magic_cookie,
-            transaction_id: transaction_id
+            class         : Class::from_u32(class)?,
+            method        : Method::from_u32(method)?,
+            length        : ((bytes[2] as u16) << 8) | bytes[3] as u16,
+            magic_cookie  : ((bytes[4] as u32) << 24) | ((bytes[5] as u32) << 16)
+                          | ((bytes[6] as u32) << 8)  | bytes[7] as u32,
+            transaction_id: TransactionId::new(transaction_id)
         })
     }
+    pub fn class(&self) -> &Class {
+        &self.class
+    }
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+    pub fn message_type(&self) -> u16 {
+        message_type(&self.class, &self.method)
+    }
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+    pub fn magic_cookie(&self) -> u32 {
+        self.magic_cookie
+    }
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+    /// Whether this is a RFC 3489 ("classic STUN") message, without the magic cookie.
+    pub fn is_classic(&self) -> bool {
+        self.magic_cookie != STUN_MAGIC_COOKIE
+    }
     pub fn set_length(&mut self, length: u16) {
         self.length = length;
     }
@@ -242,31 +296,15 @@ impl Header {
     pub fn set_method(&mut self, method: Method){
         self.method = method;
     }
-    pub fn set_transaction_id(&mut self, transaction_id: String) {
+    pub fn set_transaction_id(&mut self, transaction_id: TransactionId) {
         self.transaction_id = transaction_id;
     }
-    pub fn into_bytes(&self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = vec![];
-
-        assert_eq!(self.magic_code, 0u8);
-
-        let bits = format!("00{:02b}", self.class.to_u32() as u8 )
-                 + format!("{:012b}", self.method.to_u32() as u8).as_ref();
-
-        bytes.push(u8::from_str_radix(&bits[0.. 8], 2).unwrap());
-        bytes.push(u8::from_str_radix(&bits[8..16], 2).unwrap());
-        
-        let length_bits = format!("{:016b}", self.length);
-        bytes.push(u8::from_str_radix(&length_bits[0..8], 2).unwrap());
-        bytes.push(u8::from_str_radix(&length_bits[8..16], 2).unwrap());
-        if self.magic_cookie == STUN_MAGIC_COOKIE {
-            let mc_bits = format!("{:032b}", self.magic_code);
-            bytes.push(u8::from_str_radix(&mc_bits[ 0.. 8], 2).unwrap());
-            bytes.push(u8::from_str_radix(&mc_bits[ 8..16], 2).unwrap());
-            bytes.push(u8::from_str_radix(&mc_bits[16..24], 2).unwrap());
-            bytes.push(u8::from_str_radix(&mc_bits[24..32], 2).unwrap());
-        }
-        bytes.extend(self.transaction_id.clone().into_bytes());
+    pub fn into_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0u8; 20];
+        bytes[0..2].copy_from_slice(&self.message_type().to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.length.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.magic_cookie.to_be_bytes());
+        bytes[8..20].copy_from_slice(self.transaction_id.as_bytes());
         bytes
     }
 }
@@ -0,0 +1,279 @@
+use std::io::{self, Write};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::Sha256;
+use md5::{Md5, Digest};
+use stringprep;
+use stringprep::tables;
+use unicode_normalization::UnicodeNormalization;
+
+use super::{Packet, Attribute};
+use super::super::error::Error;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/**
+Refs:
+    https://tools.ietf.org/html/rfc5389#section-15.4
+    https://tools.ietf.org/html/rfc5389#section-10.2.2
+
+The MESSAGE-INTEGRITY attribute contains an HMAC-SHA1 of the STUN message.
+The text used as input to HMAC is the STUN message, including the header,
+up to and including the attribute preceding the MESSAGE-INTEGRITY attribute.
+The length field of the header is adjusted to point to the end of the
+MESSAGE-INTEGRITY attribute while the HMAC is computed.
+
+MESSAGE-INTEGRITY-SHA256 (RFC 8489 14.6) is computed the same way with
+HMAC-SHA256, the value may be truncated to 16 bytes (in multiples of 4).
+
+Key:
+    short-term credential: password
+    long-term credential : MD5(username ":" realm ":" password)
+                           SHA256(username ":" realm ":" password)
+**/
+pub const MESSAGE_INTEGRITY_SIZE: usize = 20;
+pub const MESSAGE_INTEGRITY_SHA256_SIZE: usize = 32;
+
+/// RFC 8489 9.2: a NONCE starting with this cookie carries the
+/// server's security feature set in the next 4 characters (base64, 24 bits).
+pub const NONCE_COOKIE: &str = "obMatJos2";
+pub const SECURITY_FEATURE_PASSWORD_ALGORITHMS: u32 = 0x800000; // bit 0
+pub const SECURITY_FEATURE_USERNAME_ANONYMITY : u32 = 0x400000; // bit 1
+
+/**
+SASLprep (RFC 4013), the string preparation RFC 5389 applies to USERNAME,
+REALM and passwords: non-ASCII spaces are mapped to SPACE, soft hyphens and
+the like are removed, the result is NFKC normalized and prohibited or
+unassigned code points are rejected.
+
+Refs:
+    https://tools.ietf.org/html/rfc4013
+**/
+pub fn saslprep(s: &str) -> Result<String, Error> {
+    match stringprep::saslprep(s) {
+        Ok(prepared) => Ok(prepared.into_owned()),
+        Err(_)       => Err(Error::StringPrep)
+    }
+}
+
+/**
+The OpaqueString profile of PRECIS (RFC 8265 4.2), which replaces SASLprep
+in RFC 8489 for REALM and passwords: non-ASCII spaces are mapped to SPACE,
+the result is NFC normalized and must be non-empty. Only the control and
+noncharacter code points of the FreeformClass are rejected.
+
+Refs:
+    https://tools.ietf.org/html/rfc8265#section-4.2
+**/
+pub fn opaque_string(s: &str) -> Result<String, Error> {
+    let prepared: String = s.chars()
+        .map(|c| if tables::non_ascii_space_character(c) { ' ' } else { c })
+        .nfc()
+        .collect();
+    if prepared.is_empty() || prepared.chars().any(|c| c.is_control() || tables::non_character_code_point(c)) {
+        return Err(Error::StringPrep);
+    }
+    Ok(prepared)
+}
+
+/// key = SASLprep(password) (RFC 5389 15.4)
+pub fn short_term_key(password: &str) -> Result<Vec<u8>, Error> {
+    Ok(saslprep(password)?.into_bytes())
+}
+
+/// key = MD5(SASLprep(username) ":" SASLprep(realm) ":" SASLprep(password))
+/// (RFC 5389 15.4), so that the key doesn't depend on how the strings were written.
+pub fn long_term_key(username: &str, realm: &str, password: &str) -> Result<Vec<u8>, Error> {
+    let mut hasher = Md5::new();
+    hasher.update(format!("{}:{}:{}", saslprep(username)?, saslprep(realm)?, saslprep(password)?).as_bytes());
+    Ok(hasher.finalize().to_vec())
+}
+
+/**
+0                   1                   2                   3
+0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|          Algorithm           |  Algorithm Parameters Length   |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                    Algorithm Parameters (variable)
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+0x0000  Reserved
+0x0001  MD5     [RFC8489]
+0x0002  SHA-256 [RFC8489]
+**/
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PasswordAlgorithm {
+    Md5,
+    Sha256,
+    Other { number: u16, #[cfg_attr(feature = "serde", serde(with = "super::serialize::hex"))] parameters: Vec<u8> }
+}
+
+impl PasswordAlgorithm {
+    pub fn to_u32(&self) -> u32 {
+        match *self {
+            PasswordAlgorithm::Md5    => 0x0001,
+            PasswordAlgorithm::Sha256 => 0x0002,
+            PasswordAlgorithm::Other { number, .. } => number as u32
+        }
+    }
+    /// Decode one algorithm, returns it with the number of bytes used (without padding).
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), Error> {
+        if bytes.len() < 4 {
+            return Err(Error::Truncated { offset: bytes.len(), needed: 4 - bytes.len() });
+        }
+        let number = ((bytes[0] as u16) << 8) | bytes[1] as u16;
+        let length = ((bytes[2] as usize) << 8) | bytes[3] as usize;
+        if bytes.len() < 4 + length {
+            return Err(Error::Truncated { offset: bytes.len(), needed: 4 + length - bytes.len() });
+        }
+        let algorithm = match (number, length) {
+            (0x0001, 0) => PasswordAlgorithm::Md5,
+            (0x0002, 0) => PasswordAlgorithm::Sha256,
+            _           => PasswordAlgorithm::Other { number, parameters: bytes[4..4 + length].to_vec() }
+        };
+        Ok((algorithm, 4 + length))
+    }
+    pub fn parameters(&self) -> &[u8] {
+        match *self {
+            PasswordAlgorithm::Other { ref parameters, .. } => parameters,
+            _ => &[]
+        }
+    }
+    pub fn into_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(4 + self.parameters().len());
+        self.write_to(&mut bytes).unwrap();
+        bytes
+    }
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let parameters = self.parameters();
+        writer.write_all(&(self.to_u32() as u16).to_be_bytes())?;
+        writer.write_all(&(parameters.len() as u16).to_be_bytes())?;
+        writer.write_all(parameters)
+    }
+    /// The long-term credential key of RFC 8489 9.2.2:
+    /// hash(OpaqueString(username) ":" OpaqueString(realm) ":" OpaqueString(password)),
+    /// `Error::PasswordAlgorithmMismatch` for algorithms we do not implement.
+    pub fn key(&self, username: &str, realm: &str, password: &str) -> Result<Vec<u8>, Error> {
+        let input = format!("{}:{}:{}", opaque_string(username)?, opaque_string(realm)?, opaque_string(password)?);
+        match *self {
+            PasswordAlgorithm::Md5    => {
+                let mut hasher = Md5::new();
+                hasher.update(input.as_bytes());
+                Ok(hasher.finalize().to_vec())
+            },
+            PasswordAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                hasher.update(input.as_bytes());
+                Ok(hasher.finalize().to_vec())
+            },
+            PasswordAlgorithm::Other { .. } => Err(Error::PasswordAlgorithmMismatch)
+        }
+    }
+}
+
+/// USERHASH (RFC 8489 14.4): SHA-256(OpaqueString(username) ":" OpaqueString(realm)),
+/// sent instead of USERNAME when the server offers username anonymity.
+pub fn user_hash(username: &str, realm: &str) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}", opaque_string(username)?, opaque_string(realm)?).as_bytes());
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hasher.finalize());
+    Ok(hash)
+}
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Build a NONCE advertising the security `features` in front of `nonce`.
+pub fn nonce_with_features(features: u32, nonce: &str) -> String {
+    let mut s = NONCE_COOKIE.to_owned();
+    for shift in [18, 12, 6, 0].iter() {
+        s.push(BASE64_ALPHABET[((features >> shift) & 0x3F) as usize] as char);
+    }
+    s.push_str(nonce);
+    s
+}
+
+/// The security feature set of a NONCE, `None` if it does not start with the nonce cookie.
+pub fn nonce_features(nonce: &str) -> Option<u32> {
+    if !nonce.starts_with(NONCE_COOKIE) || nonce.len() < NONCE_COOKIE.len() + 4 {
+        return None;
+    }
+    let mut features = 0u32;
+    for c in nonce[NONCE_COOKIE.len()..NONCE_COOKIE.len() + 4].bytes() {
+        let value = BASE64_ALPHABET.iter().position(|b| *b == c)?;
+        features = (features << 6) | value as u32;
+    }
+    Some(features)
+}
+
+/// Client side of RFC 8489 9.2.4: pick the strongest algorithm the server offers.
+pub fn choose_password_algorithm(offered: &[PasswordAlgorithm]) -> Option<PasswordAlgorithm> {
+    if offered.contains(&PasswordAlgorithm::Sha256) {
+        Some(PasswordAlgorithm::Sha256)
+    } else if offered.contains(&PasswordAlgorithm::Md5) {
+        Some(PasswordAlgorithm::Md5)
+    } else {
+        None
+    }
+}
+
+/// Server side of RFC 8489 9.2.4 (bid-down protection): the request must echo the
+/// PASSWORD-ALGORITHMS the server sent and its PASSWORD-ALGORITHM must be one of them.
+/// A request carrying neither attribute falls back to MD5.
+pub fn check_password_algorithm(offered: &[PasswordAlgorithm], request: &Packet) -> Result<PasswordAlgorithm, Error> {
+    let mut algorithms: Option<&Vec<PasswordAlgorithm>> = None;
+    let mut algorithm : Option<&PasswordAlgorithm> = None;
+    for attribute in request.attributes().iter() {
+        match *attribute {
+            Attribute::PasswordAlgorithms(ref a) => algorithms = Some(a),
+            Attribute::PasswordAlgorithm(ref a)  => algorithm  = Some(a),
+            _ => { }
+        }
+    }
+    match (algorithms, algorithm) {
+        (None, None) => Ok(PasswordAlgorithm::Md5),
+        (Some(algorithms), Some(algorithm)) => {
+            if &algorithms[..] != offered || !offered.contains(algorithm) {
+                return Err(Error::PasswordAlgorithmMismatch);
+            }
+            Ok(algorithm.clone())
+        },
+        _ => Err(Error::PasswordAlgorithmMismatch)
+    }
+}
+
+pub fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; MESSAGE_INTEGRITY_SIZE] {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    let mut hmac = [0u8; MESSAGE_INTEGRITY_SIZE];
+    hmac.copy_from_slice(&mac.finalize().into_bytes());
+    hmac
+}
+
+/// Constant time comparison of `expected` with the HMAC-SHA1 of `message`.
+pub fn verify_hmac_sha1(key: &[u8], message: &[u8], expected: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac.verify_slice(expected).is_ok()
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; MESSAGE_INTEGRITY_SHA256_SIZE] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    let mut hmac = [0u8; MESSAGE_INTEGRITY_SHA256_SIZE];
+    hmac.copy_from_slice(&mac.finalize().into_bytes());
+    hmac
+}
+
+/// Constant time comparison of a (possibly truncated) `expected` with the HMAC-SHA256 of `message`.
+pub fn verify_hmac_sha256(key: &[u8], message: &[u8], expected: &[u8]) -> bool {
+    if expected.len() < 16 {
+        return false;
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac.verify_truncated_left(expected).is_ok()
+}
@@ -2,40 +2,348 @@
 use std::str::FromStr;
 use std::string::ToString;
 use std::convert::AsRef;
+use std::cmp;
 
 use std::io::{Read, Write};
 
 pub mod header;
+pub mod transaction_id;
 pub mod attribute;
 pub mod error_code;
 
 pub mod address;
+pub mod integrity;
+pub mod fingerprint;
+pub mod packet_ref;
+pub mod builder;
+pub mod channel_data;
+#[cfg(feature = "serde")]
+mod serialize;
 
-pub use self::header::{Header, Method, Class};
-pub use self::attribute::{AttributeType, Attribute};
+pub use self::header::{Header, Method, Class, bytes_to_hex_str};
+pub use self::transaction_id::TransactionId;
+pub use self::attribute::{AttributeType, Attribute, padding_len, is_comprehension_required};
 pub use self::address::{Address, Family};
 pub use self::error_code::ErrorCode;
+pub use self::integrity::PasswordAlgorithm;
+pub use self::packet_ref::PacketRef;
+pub use self::builder::MessageBuilder;
+pub use self::channel_data::ChannelData;
 
-#[derive(Debug)]
+use super::error::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+pub const HEADER_SIZE: usize = 20;
+pub const FINGERPRINT_SIZE: usize = 8;
+
+/**
+0                   1                   2                   3
+0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|         Type                  |            Length             |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                         Value (variable)                ....
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+Figure 4: Format of STUN Attributes
+
+A STUN message is a 20 bytes `Header` followed by zero or more attributes,
+every attribute value is padded to a multiple of 4 bytes and the header
+length counts the attributes (with padding) but not the header itself.
+
+The padding bytes may hold any value and are ignored by receivers, `padding`
+is the byte the encoder pads with. A decoded packet keeps the padding byte
+it was sent with so it is re-encoded to the same bytes.
+**/
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Packet {
-    header    : Header,
-    attributes: Vec<Attribute>
+    header     : Header,
+    attributes : Vec<Attribute>,
+    fingerprint: bool,
+    padding    : u8
 }
 
 impl Packet {
-    pub fn new(header: Header) -> Result<Self, &'static str> {
+    pub fn new(header: Header) -> Result<Self, Error> {
+        let mut header = header;
+        header.set_length(0);
+        Ok(Packet {
+            header,
+            attributes : Vec::new(),
+            fingerprint: false,
+            padding    : 0
+        })
+    }
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+    /// The ERROR-CODE of an error response, with the reason phrase sent by the peer.
+    pub fn error_code(&self) -> Option<(&ErrorCode, &str)> {
+        self.attributes.iter()
+            .filter_map(|attribute| match *attribute {
+                Attribute::ErrorCode(ref code, ref reason) => Some((code, reason.as_str())),
+                _ => None
+            })
+            .next()
+    }
+    /// The types of the comprehension-required attributes this implementation
+    /// does not understand. A request carrying any of them must be answered with
+    /// a 420 (Unknown Attribute) error listing them, other messages are discarded.
+    pub fn unknown_comprehension_required(&self) -> Vec<u16> {
+        self.attributes.iter()
+            .filter_map(|attribute| match *attribute {
+                Attribute::Unknown { typ, .. } if is_comprehension_required(typ) => Some(typ),
+                _ => None
+            })
+            .collect()
+    }
+    /// Whether a FINGERPRINT attribute is appended (last) when the packet is encoded.
+    pub fn fingerprint(&self) -> bool {
+        self.fingerprint
+    }
+    pub fn padding(&self) -> u8 {
+        self.padding
+    }
+    pub fn set_padding(&mut self, padding: u8) {
+        self.padding = padding;
+    }
+    pub fn set_fingerprint(&mut self, fingerprint: bool) {
+        if fingerprint != self.fingerprint {
+            let length = self.header.length() as usize;
+            let length = if fingerprint { length + FINGERPRINT_SIZE } else { length - FINGERPRINT_SIZE };
+            self.header.set_length(length as u16);
+            self.fingerprint = fingerprint;
+        }
+    }
+    /// FINGERPRINT is computed while encoding, adding `Attribute::FingerPrint`
+    /// is the same as `set_fingerprint(true)`.
+    pub fn add_attribute(&mut self, attribute: Attribute) {
+        if let Attribute::FingerPrint(_) = attribute {
+            return self.set_fingerprint(true);
+        }
+        // A length past u16::MAX is reported by `into_bytes`.
+        let length = self.header.length() as usize + attribute.encoded_len(&self.header);
+        self.header.set_length(cmp::min(length, u16::MAX as usize) as u16);
+        self.attributes.push(attribute);
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let header = Header::from_bytes(bytes)?;
+        let length = header.length() as usize;
+
+        if length % 4 != 0 {
+            return Err(Error::UnalignedLength(length));
+        }
+        if bytes.len() != HEADER_SIZE + length {
+            return Err(Error::LengthMismatch { declared: length, actual: bytes.len() - HEADER_SIZE });
+        }
+
+        let mut attributes: Vec<Attribute> = Vec::new();
+        let mut fingerprint = false;
+        let mut padding: Option<u8> = None;
+        let mut offset = HEADER_SIZE;
+        while offset < bytes.len() {
+            if offset + 4 > bytes.len() {
+                return Err(Error::Truncated { offset: bytes.len(), needed: offset + 4 - bytes.len() });
+            }
+            let attr_type   = ((bytes[offset] as u32) << 8) | bytes[offset + 1] as u32;
+            let attr_length = ((bytes[offset + 2] as usize) << 8) | bytes[offset + 3] as usize;
+            let value_start = offset + 4;
+            let value_end   = value_start + attr_length;
+            if value_end + padding_len(attr_length) > bytes.len() {
+                return Err(Error::Truncated {
+                    offset: bytes.len(),
+                    needed: value_end + padding_len(attr_length) - bytes.len()
+                });
+            }
+            let value = &bytes[value_start..value_end];
+            let attribute = match AttributeType::from_u32(attr_type) {
+                Ok(known) => match Attribute::from_bytes(known, value, &header) {
+                    Ok(attribute) => attribute,
+                    Err(e)        => return Err(Error::Attribute {
+                        typ   : attr_type as u16,
+                        offset,
+                        error : Box::new(e)
+                    })
+                },
+                Err(_)        => Attribute::Unknown { typ: attr_type as u16, value: value.to_vec() }
+            };
+            if let Attribute::FingerPrint(crc) = attribute {
+                if value_end != bytes.len() {
+                    return Err(Error::FingerprintNotLast);
+                }
+                let expected = fingerprint::fingerprint(&bytes[..offset]);
+                if crc != expected {
+                    return Err(Error::FingerprintMismatch { expected, actual: crc });
+                }
+                fingerprint = true;
+            } else {
+                attributes.push(attribute);
+            }
+            if padding.is_none() && padding_len(attr_length) > 0 {
+                padding = Some(bytes[value_end]);
+            }
+            offset = value_end + padding_len(attr_length);
+        }
+
         Ok(Packet {
-            header    : header,
-            attributes: Vec::new()
+            header,
+            attributes,
+            fingerprint,
+            padding    : padding.unwrap_or(0)
         })
     }
-    pub fn from_bytes(&self) -> Result<Self, &'static str> {
-        unimplemented!();
+    pub fn into_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.encode_attributes(0)?;
+        if self.fingerprint {
+            append_fingerprint(&mut bytes);
+        }
+        Ok(bytes)
+    }
+    /// Encode the header and the attributes, `trailer` is the size of the
+    /// MESSAGE-INTEGRITY attribute the caller appends (FINGERPRINT is accounted for).
+    fn encode_attributes(&self, trailer: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.header.into_bytes().to_vec();
+        for attribute in self.attributes.iter() {
+            let offset = bytes.len();
+            bytes.resize(offset + attribute.encoded_len(&self.header), 0);
+            attribute.encode(&self.header, self.padding, &mut bytes[offset..])?;
+        }
+        let fingerprint_size = if self.fingerprint { FINGERPRINT_SIZE } else { 0 };
+        let length = bytes.len() - HEADER_SIZE + trailer + fingerprint_size;
+        if length > u16::MAX as usize {
+            return Err(Error::MessageTooLarge { max: u16::MAX as usize, actual: length });
+        }
+        let body_length = bytes.len() - HEADER_SIZE;
+        set_message_length(&mut bytes, body_length);
+        Ok(bytes)
     }
-    pub fn into_bytes(&self) -> &[u8] {
-        unimplemented!();
+    /// Encode the packet and append a MESSAGE-INTEGRITY attribute computed
+    /// with `key` (see `integrity::short_term_key` and `integrity::long_term_key`).
+    pub fn into_bytes_with_integrity(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.without_integrity().encode_attributes(4 + integrity::MESSAGE_INTEGRITY_SIZE)?;
+        append_message_integrity(&mut bytes, key);
+        if self.fingerprint {
+            append_fingerprint(&mut bytes);
+        }
+        Ok(bytes)
     }
-    pub fn to_hex_string(&self) -> String {
-        unimplemented!();
+    /// Same as `into_bytes_with_integrity` with MESSAGE-INTEGRITY-SHA256 (RFC 8489),
+    /// `key` comes from `PasswordAlgorithm::key` for long-term credentials.
+    pub fn into_bytes_with_integrity_sha256(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.without_integrity().encode_attributes(4 + integrity::MESSAGE_INTEGRITY_SHA256_SIZE)?;
+        append_message_integrity_sha256(&mut bytes, key);
+        if self.fingerprint {
+            append_fingerprint(&mut bytes);
+        }
+        Ok(bytes)
     }
-}
\ No newline at end of file
+    fn without_integrity(&self) -> Self {
+        let mut packet = Packet::new(self.header.clone()).unwrap();
+        packet.padding = self.padding;
+        for attribute in self.attributes.iter() {
+            match *attribute {
+                Attribute::MessageIntegrity(_) | Attribute::MessageIntegritySha256(_) => { },
+                _ => packet.add_attribute(attribute.clone())
+            }
+        }
+        packet
+    }
+    /// Decode the message and check its integrity with `key`. MESSAGE-INTEGRITY-SHA256
+    /// is used when present, MESSAGE-INTEGRITY otherwise; attributes following the
+    /// checked one are ignored as required by RFC 5389 / RFC 8489.
+    pub fn verify(bytes: &[u8], key: &[u8]) -> Result<Self, Error> {
+        match Packet::verify_sha256(bytes, key) {
+            Err(Error::MissingIntegrity) => Packet::verify_with(bytes, key, AttributeType::MessageIntegrity),
+            result => result
+        }
+    }
+    /// Like `verify` but only accepts MESSAGE-INTEGRITY-SHA256.
+    pub fn verify_sha256(bytes: &[u8], key: &[u8]) -> Result<Self, Error> {
+        Packet::verify_with(bytes, key, AttributeType::MessageIntegritySha256)
+    }
+    fn verify_with(bytes: &[u8], key: &[u8], attr_type: AttributeType) -> Result<Self, Error> {
+        let mut packet = Packet::from_bytes(bytes)?;
+        let (index, offset, length) = match find_attribute(bytes, attr_type.to_u32() as u16) {
+            Some(position) => position,
+            None           => return Err(Error::MissingIntegrity)
+        };
+
+        let mut message = bytes[..offset].to_vec();
+        set_message_length(&mut message, offset - HEADER_SIZE + 4 + length);
+        let expected = &bytes[offset + 4..offset + 4 + length];
+        let verified = match attr_type {
+            AttributeType::MessageIntegritySha256 => integrity::verify_hmac_sha256(key, &message, expected),
+            _ => integrity::verify_hmac_sha1(key, &message, expected)
+        };
+        if !verified {
+            return Err(Error::IntegrityCheckFailure);
+        }
+
+        packet.attributes.truncate(index + 1);
+        let fingerprint_size = if packet.fingerprint { FINGERPRINT_SIZE } else { 0 };
+        packet.header.set_length((offset + 4 + length - HEADER_SIZE + fingerprint_size) as u16);
+        Ok(packet)
+    }
+    pub fn to_hex_string(&self) -> Result<String, Error> {
+        Ok(bytes_to_hex_str(&self.into_bytes()?))
+    }
+}
+
+fn set_message_length(bytes: &mut [u8], length: usize) {
+    bytes[2] = (length >> 8) as u8;
+    bytes[3] = length as u8;
+}
+
+/// Walk the attributes of a well formed message and return the index, the
+/// byte offset and the value length of the first attribute of type `typ`.
+fn find_attribute(bytes: &[u8], typ: u16) -> Option<(usize, usize, usize)> {
+    let mut index  = 0;
+    let mut offset = HEADER_SIZE;
+    while offset + 4 <= bytes.len() {
+        let attr_type   = ((bytes[offset] as u16) << 8) | bytes[offset + 1] as u16;
+        let attr_length = ((bytes[offset + 2] as usize) << 8) | bytes[offset + 3] as usize;
+        if attr_type == typ {
+            return Some((index, offset, attr_length));
+        }
+        index  += 1;
+        offset += 4 + attr_length + padding_len(attr_length);
+    }
+    None
+}
+
+/// Append a MESSAGE-INTEGRITY attribute to an encoded message, the header
+/// length is updated to cover the new attribute before the HMAC is computed.
+pub fn append_message_integrity(bytes: &mut Vec<u8>, key: &[u8]) {
+    let length = bytes.len() - HEADER_SIZE + 4 + integrity::MESSAGE_INTEGRITY_SIZE;
+    set_message_length(bytes, length);
+    let hmac = integrity::hmac_sha1(key, bytes);
+    append_attribute(bytes, AttributeType::MessageIntegrity, &hmac);
+}
+
+/// Append a MESSAGE-INTEGRITY-SHA256 attribute (full 32 bytes HMAC) to an encoded message.
+pub fn append_message_integrity_sha256(bytes: &mut Vec<u8>, key: &[u8]) {
+    let length = bytes.len() - HEADER_SIZE + 4 + integrity::MESSAGE_INTEGRITY_SHA256_SIZE;
+    set_message_length(bytes, length);
+    let hmac = integrity::hmac_sha256(key, bytes);
+    append_attribute(bytes, AttributeType::MessageIntegritySha256, &hmac);
+}
+
+/// Append a FINGERPRINT attribute to an encoded message, it must be the last attribute.
+pub fn append_fingerprint(bytes: &mut Vec<u8>) {
+    let length = bytes.len() - HEADER_SIZE + FINGERPRINT_SIZE;
+    set_message_length(bytes, length);
+    let crc = fingerprint::fingerprint(bytes);
+    append_attribute(bytes, AttributeType::FingerPrint, &crc.to_be_bytes());
+}
+
+fn append_attribute(bytes: &mut Vec<u8>, attr_type: AttributeType, value: &[u8]) {
+    bytes.extend(&(attr_type.to_u32() as u16).to_be_bytes());
+    bytes.extend(&(value.len() as u16).to_be_bytes());
+    bytes.extend(value);
+}
@@ -0,0 +1,133 @@
+use super::{Packet, Header, TransactionId, AttributeType, padding_len, HEADER_SIZE};
+use super::super::error::Error;
+
+/**
+A borrowed view of an encoded STUN message.
+
+`PacketRef::new` only checks the framing (the header size, the two leading
+zero bits and the message length), the class, method and attributes are
+decoded on demand and attribute values are handed out as slices of the
+original buffer, so nothing is allocated until `to_packet` is called.
+**/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketRef<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> PacketRef<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::Truncated { offset: bytes.len(), needed: HEADER_SIZE - bytes.len() });
+        }
+        if bytes[0] & 0xC0 != 0 {
+            return Err(Error::InvalidMessageType(((bytes[0] as u32) << 8) | bytes[1] as u32));
+        }
+        let length = ((bytes[2] as usize) << 8) | bytes[3] as usize;
+        if length % 4 != 0 {
+            return Err(Error::UnalignedLength(length));
+        }
+        if bytes.len() != HEADER_SIZE + length {
+            return Err(Error::LengthMismatch { declared: length, actual: bytes.len() - HEADER_SIZE });
+        }
+        Ok(PacketRef { bytes })
+    }
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+    /// Decode the header, fails on an unknown class or method.
+    pub fn header(&self) -> Result<Header, Error> {
+        Header::from_bytes(self.bytes)
+    }
+    pub fn message_type(&self) -> u16 {
+        ((self.bytes[0] as u16) << 8) | self.bytes[1] as u16
+    }
+    pub fn length(&self) -> u16 {
+        ((self.bytes[2] as u16) << 8) | self.bytes[3] as u16
+    }
+    pub fn magic_cookie(&self) -> u32 {
+        ((self.bytes[4] as u32) << 24) | ((self.bytes[5] as u32) << 16)
+            | ((self.bytes[6] as u32) << 8) | self.bytes[7] as u32
+    }
+    pub fn transaction_id(&self) -> TransactionId {
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&self.bytes[8..HEADER_SIZE]);
+        TransactionId::new(transaction_id)
+    }
+    pub fn attributes(&self) -> Attributes<'a> {
+        Attributes { raw: self.raw_attributes() }
+    }
+    /// The value of the first attribute of type `attr_type`, the other
+    /// attributes are skipped without being decoded.
+    pub fn attribute(&self, attr_type: AttributeType) -> Option<&'a [u8]> {
+        let typ = attr_type.to_u32() as u16;
+        for attribute in self.raw_attributes() {
+            match attribute {
+                Ok((t, value)) if t == typ => return Some(value),
+                Ok(_)  => { },
+                Err(_) => return None
+            }
+        }
+        None
+    }
+    /// Like `attributes` but yields the raw attribute types, known or not.
+    pub fn raw_attributes(&self) -> RawAttributes<'a> {
+        RawAttributes { bytes: self.bytes, offset: HEADER_SIZE }
+    }
+    /// Decode the whole message into an owned `Packet`.
+    pub fn to_packet(&self) -> Result<Packet, Error> {
+        Packet::from_bytes(self.bytes)
+    }
+}
+
+/// Iterator over the `(type, value)` pairs of a message, the value does not
+/// include the padding. An attribute overrunning the message ends the
+/// iteration with `Error::Truncated`.
+#[derive(Debug, Clone)]
+pub struct RawAttributes<'a> {
+    bytes : &'a [u8],
+    offset: usize
+}
+
+impl<'a> Iterator for RawAttributes<'a> {
+    type Item = Result<(u16, &'a [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes  = self.bytes;
+        let offset = self.offset;
+        if offset >= bytes.len() {
+            return None;
+        }
+        if offset + 4 > bytes.len() {
+            self.offset = bytes.len();
+            return Some(Err(Error::Truncated { offset: bytes.len(), needed: offset + 4 - bytes.len() }));
+        }
+        let typ    = ((bytes[offset] as u16) << 8) | bytes[offset + 1] as u16;
+        let length = ((bytes[offset + 2] as usize) << 8) | bytes[offset + 3] as usize;
+        let end    = offset + 4 + length;
+        if end + padding_len(length) > bytes.len() {
+            self.offset = bytes.len();
+            return Some(Err(Error::Truncated { offset: bytes.len(), needed: end + padding_len(length) - bytes.len() }));
+        }
+        self.offset = end + padding_len(length);
+        Some(Ok((typ, &bytes[offset + 4..end])))
+    }
+}
+
+/// Iterator over the `(AttributeType, value)` pairs of a message, an
+/// attribute of unknown type yields `Error::UnknownAttribute` (or
+/// `Error::ReservedAttribute`) and the iteration goes on with the next one.
+#[derive(Debug, Clone)]
+pub struct Attributes<'a> {
+    raw: RawAttributes<'a>
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Result<(AttributeType, &'a [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|attribute| {
+            let (typ, value) = attribute?;
+            Ok((AttributeType::from_u32(typ as u32)?, value))
+        })
+    }
+}
@@ -0,0 +1,105 @@
+/*
+Serde support, behind the `serde` feature.
+
+Messages are written in a form meant to be read by people (logs, test
+fixtures): classes, methods and address families by name, addresses as
+`ip:port` strings, transaction IDs and other byte strings as lowercase hex,
+error codes as their number and reason phrase, e.g. (addresses look like
+`{ "XorMappedAddress": "192.0.2.1:32853" }`):
+{
+  "header": {
+    "class": "FailureResponse",
+    "method": "Binding",
+    "length": 32,
+    "magic_cookie": 554869826,
+    "transaction_id": "b7e7a701bc34d686fa87dfae"
+  },
+  "attributes": [
+    { "ErrorCode": { "code": 420, "reason": "Unknown Attribute" } },
+    { "UnknownAttribute": [28673] }
+  ],
+  "fingerprint": false,
+  "padding": 0
+}
+*/
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use super::{ErrorCode, TransactionId};
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `#[serde(with = "hex")]` for the byte string fields.
+pub mod hex {
+    use std::convert::TryFrom;
+
+    use serde::{Serializer, Deserialize, Deserializer};
+    use serde::de::Error as DeError;
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(bytes.as_ref()))
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(deserializer: D) -> Result<T, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = super::from_hex(&s).ok_or_else(|| D::Error::custom(format!("invalid hex string {:?}", s)))?;
+        let length = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::invalid_length(length, &"a byte string of the attribute size"))
+    }
+}
+
+/// `#[serde(with = "error_code_attribute")]` for `Attribute::ErrorCode`, so
+/// that the attribute keeps its own reason phrase.
+pub mod error_code_attribute {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::Error as DeError;
+
+    use super::{ErrorCode, ErrorCodeRepr};
+
+    pub fn serialize<S: Serializer>(code: &ErrorCode, reason: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorCodeRepr { code: code.to_u32() as u16, reason: reason.to_owned() }.serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(ErrorCode, String), D::Error> {
+        let repr = ErrorCodeRepr::deserialize(deserializer)?;
+        let code = ErrorCode::from_code(repr.code as u32, &repr.reason).map_err(D::Error::custom)?;
+        Ok((code, repr.reason))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorCodeRepr {
+    code  : u16,
+    reason: String
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        error_code_attribute::serialize(self, &self.to_string(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        error_code_attribute::deserialize(deserializer).map(|(code, _)| code)
+    }
+}
+
+impl Serialize for TransactionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        hex::serialize(self.as_bytes(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        hex::deserialize::<D, [u8; 12]>(deserializer).map(TransactionId::new)
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use rand::{OsRng, Rng};
+
+use super::super::error::Error;
+use super::super::constant::STUN_MAGIC_COOKIE;
+
+/**
+The transaction ID is a 96-bit identifier, used to uniquely identify
+STUN transactions.  For request/response transactions, the transaction
+ID is chosen by the STUN client for the request and echoed by the
+server in the response.  For indications, it is chosen by the agent
+sending the indication.  It primarily serves to correlate requests
+with responses, though it also plays a small role in helping to
+prevent certain types of attacks.  The server also uses the
+transaction ID as a key to identify each transaction uniquely across
+all clients.  As such, the transaction ID MUST be uniformly and
+randomly chosen from the interval 0 .. 2**96-1, and SHOULD be
+cryptographically random.
+
+Refs:
+    https://tools.ietf.org/html/rfc5389#section-6
+
+RFC 3489 messages carry a 128-bit transaction ID, its first 32 bits sit
+where RFC 5389 puts the magic cookie (see `Header::magic_cookie`).
+**/
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TransactionId([u8; 12]);
+
+impl TransactionId {
+    pub fn new(bytes: [u8; 12]) -> Self {
+        TransactionId(bytes)
+    }
+    /// A new transaction ID from the operating system's secure random source.
+    pub fn random() -> Result<Self, Error> {
+        let mut bytes = [0u8; 12];
+        OsRng::new()?.fill_bytes(&mut bytes);
+        Ok(TransactionId(bytes))
+    }
+    /// A new 128-bit RFC 3489 transaction ID (see `Header::new_classic`) from
+    /// the operating system's secure random source, its first 32 bits never
+    /// match the magic cookie.
+    pub fn random_classic() -> Result<[u8; 16], Error> {
+        let mut rng = OsRng::new()?;
+        let mut bytes = [0u8; 16];
+        loop {
+            rng.fill_bytes(&mut bytes);
+            if bytes[..4] != STUN_MAGIC_COOKIE.to_be_bytes() {
+                return Ok(bytes);
+            }
+        }
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 12 {
+            return Err(Error::InvalidLength { expected: 12, actual: bytes.len() });
+        }
+        let mut id = [0u8; 12];
+        id.copy_from_slice(bytes);
+        Ok(TransactionId(id))
+    }
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        &self.0
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TransactionId({})", self)
+    }
+}
@@ -1,42 +1,127 @@
 
 use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::string::ToString;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::net::{SocketAddr, IpAddr, TcpListener, TcpStream, UdpSocket, Shutdown};
 
-use super::{url_parse, STUN_PORT, STUNS_PORT};
+use super::{url_parse, Error, STUN_PORT, STUNS_PORT};
 use super::{packet};
+use super::framing::{FrameReader, Framing, write_frame};
+
+/// A response built by `handle`: its size, the address it must be sent from
+/// and the address it must be sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reply {
+    pub size       : usize,
+    pub source     : SocketAddr,
+    pub destination: SocketAddr
+}
 
 pub fn handler(msg: &[u8], response: &mut [u8], 
-    peer_socket_addr: &SocketAddr, local_socket_addr: &SocketAddr) -> Result<usize, &'static str>{
+    peer_socket_addr: &SocketAddr, local_socket_addr: &SocketAddr) -> Result<usize, Error>{
+    handle(msg, response, peer_socket_addr, local_socket_addr, None).map(|reply| reply.size)
+}
 
-    println!("[Handler] Local Addr: {:?} <-- Peer Addr: {:?}", local_socket_addr, peer_socket_addr);
+/**
+Answer a Binding request received on `local_socket_addr` from `peer_socket_addr`,
+`alternate_socket_addr` is the server's other IP address and port, needed to
+honour CHANGE-REQUEST. Requests of any other method get a 400 (Bad Request).
 
-    match packet::Header::from_bytes(&msg[..20]) {
-        Ok(mut head) => {
-            println!("[DEBUG] STUN Request Head: {:?}", head);
+RFC 5389 clients get a XOR-MAPPED-ADDRESS, and RESPONSE-ORIGIN and OTHER-ADDRESS
+when the server has an alternate address (RFC 5780), the response goes to the
+port in RESPONSE-PORT when the request carries one. RFC 3489 clients (no magic cookie)
+get MAPPED-ADDRESS, SOURCE-ADDRESS and CHANGED-ADDRESS (only when the server has
+an alternate address, so they never test against the same one), and the response goes
+to RESPONSE-ADDRESS when the request carries one, with REFLECTED-FROM set to
+the request's source. So that the server can't be used to reflect traffic to
+a third party, RESPONSE-ADDRESS is only honoured when it shares its IP address
+with the request's source, the response is sent back to the source otherwise.
 
-            let attr = packet::Attribute::MappedAddress(peer_socket_addr.clone());
+Refs:
+    https://tools.ietf.org/html/rfc3489#section-8.2
+    https://tools.ietf.org/html/rfc5780#section-7.2
+**/
+pub fn handle(msg: &[u8], response: &mut [u8], peer_socket_addr: &SocketAddr,
+    local_socket_addr: &SocketAddr, alternate_socket_addr: Option<&SocketAddr>) -> Result<Reply, Error> {
+    let request = packet::Packet::from_bytes(msg)?;
+    let head    = request.header();
 
-            let attr_bytes: Vec<u8> = attr.into_bytes();
-            let attr_length = attr_bytes.len() as u16;
+    let mut reply = Reply { size: 0, source: *local_socket_addr, destination: *peer_socket_addr };
 
-            head.set_class(packet::header::Class::SuccessResponse);
-            head.set_length(attr_length);
+    // https://tools.ietf.org/html/rfc5389#section-7.3
+    // Only requests are answered, indications (and responses) carrying unknown
+    // comprehension-required attributes are silently discarded.
+    if head.class() != &packet::Class::Request {
+        return Ok(reply);
+    }
 
-            let mut stun_packet: Vec<u8> = vec![];
+    // Binding is the only method this server implements.
+    if head.method() != &packet::Method::Binding {
+        reply.size = packet::MessageBuilder::response(response, head, packet::Class::FailureResponse)?
+            .attribute(&packet::Attribute::from(packet::ErrorCode::BadRequest))?
+            .finish()?;
+        return Ok(reply);
+    }
 
-            stun_packet.extend(head.into_bytes());
-            stun_packet.extend(attr_bytes);
-            for idx in 0..stun_packet.len() {
-                response[idx] = stun_packet[idx];
-            }
-            println!("[DEBUG] STUN Response: {:?}", stun_packet);
-            Ok(stun_packet.len())
-        },
-        Err(e)   => Err(e)
+    let mut unknown = request.unknown_comprehension_required();
+    let mut response_address = None;
+    let mut response_port    = None;
+    for attribute in request.attributes() {
+        match *attribute {
+            packet::Attribute::ChangeRequest { change_ip, change_port } => {
+                match alternate_socket_addr {
+                    Some(alternate) => {
+                        let ip   = if change_ip { alternate.ip() } else { local_socket_addr.ip() };
+                        let port = if change_port { alternate.port() } else { local_socket_addr.port() };
+                        reply.source = SocketAddr::new(ip, port);
+                    },
+                    None if change_ip || change_port => unknown.push(attribute.typ()),
+                    None => { }
+                }
+            },
+            packet::Attribute::ResponseAddress(socket_addr) if socket_addr.ip() == peer_socket_addr.ip() => {
+                response_address = Some(socket_addr);
+            },
+            packet::Attribute::ResponsePort(port) if !head.is_classic() => response_port = Some(port),
+            _ => { }
+        }
     }
+
+    if !unknown.is_empty() {
+        reply.source = *local_socket_addr;
+        reply.size = packet::MessageBuilder::response(response, head, packet::Class::FailureResponse)?
+            .attribute(&packet::Attribute::from(packet::ErrorCode::UnknownAttribute))?
+            .attribute(&packet::Attribute::UnknownAttribute(unknown))?
+            .finish()?;
+    } else if head.is_classic() {
+        let mut builder = packet::MessageBuilder::response(response, head, packet::Class::SuccessResponse)?
+            .attribute(&packet::Attribute::MappedAddress(*peer_socket_addr))?
+            .attribute(&packet::Attribute::SourceAddress(reply.source))?;
+        if let Some(alternate) = alternate_socket_addr {
+            builder = builder.attribute(&packet::Attribute::ChangedAddress(*alternate))?;
+        }
+        if let Some(socket_addr) = response_address {
+            builder = builder.attribute(&packet::Attribute::ReflectedFrom(*peer_socket_addr))?;
+            reply.destination = socket_addr;
+        }
+        reply.size = builder.finish()?;
+    } else {
+        let mut builder = packet::MessageBuilder::response(response, head, packet::Class::SuccessResponse)?
+            .attribute(&packet::Attribute::XorMappedAddress(*peer_socket_addr))?;
+        if let Some(alternate) = alternate_socket_addr {
+            builder = builder.attribute(&packet::Attribute::ResponseOrigin(reply.source))?
+                .attribute(&packet::Attribute::OtherAddress(*alternate))?;
+        }
+        if let Some(port) = response_port {
+            reply.destination.set_port(port);
+        }
+        reply.size = builder.finish()?;
+    }
+    Ok(reply)
 }
 
 pub fn tcp_server(host: &str){
@@ -44,65 +129,161 @@ pub fn tcp_server(host: &str){
     let listener = TcpListener::bind(socket_addr).unwrap();
     println!("[TCP Server] server running at : {:?}", listener);
 
-    let mut buf = [0; 2048];
-    let mut response = [0; 2048];
-
     for stream in listener.incoming() {
         match stream {
-            Ok(mut stream) => {
-                // thread::spawn(move || tcp_handler(&mut stream));
-                match stream.read(&mut buf[..]){
-                    Ok(size) => {
-                        let msg = &buf[..size];
-                        match handler(&msg, &mut response, 
-                            &stream.peer_addr().unwrap(), &socket_addr ) {
-                            Ok(size) => {
-                                if size > 0 {
-                                    stream.write(&response[..size]);
-                                }
-                            },
-                            Err(_) => {}
-                        }
-                    },
-                    Err(_)   => {}
-                };
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = tcp_handler(stream, &socket_addr) {
+                        println!("[Error] {:?}", e);
+                    }
+                });
             },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => { },
             Err(e) => println!("[Error] {:?}", e)
         };
     }
 }
 
-pub fn udp_server(host: &str){
+/// Answer every STUN message of a TCP connection until the peer closes it,
+/// the other frames (ChannelData) are ignored.
+pub fn tcp_handler(stream: TcpStream, local_socket_addr: &SocketAddr) -> Result<(), Error> {
+    let peer_socket_addr = stream.peer_addr()?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = FrameReader::new(stream, Framing::Native);
+    let mut response = [0; 2048];
+
+    while let Some(frame) = reader.read_frame()? {
+        if frame[0] >> 6 != 0 {
+            continue;
+        }
+        match handler(&frame, &mut response, &peer_socket_addr, local_socket_addr) {
+            Ok(0)    => { },
+            Ok(size) => write_frame(&mut writer, Framing::Native, &response[..size])?,
+            Err(e)   => println!("[Error] {:?}", e)
+        }
+    }
+    Ok(())
+}
+
+/**
+Serve Binding requests over UDP on `host`.
+
+With an `alternate` address, another IP address and port of this host, the
+server also listens on the three other combinations of the two IP addresses
+and ports, so that it can honour CHANGE-REQUEST (RFC 3489) and advertise
+CHANGED-ADDRESS / OTHER-ADDRESS (RFC 5780). Both addresses must be specific
+and differ in IP address and port.
+
+Refs:
+    https://tools.ietf.org/html/rfc5780#section-7.1
+**/
+pub fn udp_server(host: &str, alternate: Option<&str>){
     let socket_addr = url_parse(host).expect("local uri format error.");
-    let mut socket  = UdpSocket::bind(socket_addr).unwrap();
-    println!("[UDP Server] server running on {} ...", socket_addr);
+    let alternate   = alternate.map(|uri| url_parse(uri).expect("alternate uri format error."));
+    let mut socket_addrs = vec![socket_addr];
+    if let Some(alternate) = alternate {
+        if socket_addr.ip().is_unspecified() || alternate.ip().is_unspecified()
+            || socket_addr.ip() == alternate.ip() || socket_addr.port() == alternate.port() {
+            panic!("[Error] alternate address {} must differ from {} in IP address and port", alternate, socket_addr);
+        }
+        socket_addrs.push(SocketAddr::new(socket_addr.ip(), alternate.port()));
+        socket_addrs.push(SocketAddr::new(alternate.ip(), socket_addr.port()));
+        socket_addrs.push(alternate);
+    }
+    let sockets: HashMap<SocketAddr, UdpSocket> = socket_addrs.iter()
+        .map(|socket_addr| (*socket_addr, UdpSocket::bind(socket_addr).unwrap()))
+        .collect();
+    let sockets = Arc::new(sockets);
+    let stop    = Arc::new(AtomicBool::new(false));
+
+    let handles: Vec<thread::JoinHandle<()>> = socket_addrs.iter().map(|local_socket_addr| {
+        println!("[UDP Server] server running on {} ...", local_socket_addr);
+        let local_socket_addr = *local_socket_addr;
+        // The alternate of each socket differs from it in both IP address and port.
+        let other = alternate.map(|alternate| {
+            let ip   = if local_socket_addr.ip() == socket_addr.ip() { alternate.ip() } else { socket_addr.ip() };
+            let port = if local_socket_addr.port() == socket_addr.port() { alternate.port() } else { socket_addr.port() };
+            SocketAddr::new(ip, port)
+        });
+        let sockets = sockets.clone();
+        let stop    = stop.clone();
+        thread::spawn(move || udp_handler(&sockets, &local_socket_addr, other.as_ref(), &stop))
+    }).collect();
+    for handle in handles {
+        handle.join();
+    }
+}
+
+/// Answer the Binding requests received on the socket bound to `local_socket_addr`
+/// in `sockets`, each response is sent from the socket bound to its source address.
+/// Returns once `stop` is set, which a socket without a read timeout only
+/// notices when its next datagram arrives.
+pub fn udp_handler(sockets: &HashMap<SocketAddr, UdpSocket>, local_socket_addr: &SocketAddr,
+    alternate_socket_addr: Option<&SocketAddr>, stop: &AtomicBool) {
+    let socket = &sockets[local_socket_addr];
     let mut buf = [0; 2048];
     let mut response = [0; 2048];
-    loop {
+    let mut routes = HashMap::new();
+    while !stop.load(Ordering::SeqCst) {
         match socket.recv_from(&mut buf) {
             Ok((size, peer_socket_addr)) => {
                 println!("[INFO] Connection: {:?}", peer_socket_addr);
-                let msg = &buf[..size];      
-                // thread::spawn(move || handler(&msg, &mut response));
-                match handler(&msg, &mut response, &peer_socket_addr, &socket_addr) {
-                    Ok(size) => {
-                        if size > 0 {
-                            socket.send_to(&response[..size], &peer_socket_addr);
+                let local = match route_local_addr(local_socket_addr, &peer_socket_addr, &mut routes) {
+                    Ok(local) => local,
+                    Err(e)    => { println!("[Error] {:?}", e); continue; }
+                };
+                match handle(&buf[..size], &mut response, &peer_socket_addr, &local, alternate_socket_addr) {
+                    Ok(reply) if reply.size > 0 => {
+                        // A socket bound to any address answers from the routed one.
+                        let sender = sockets.get(&reply.source).unwrap_or(socket);
+                        if let Err(e) = sender.send_to(&response[..reply.size], reply.destination) {
+                            println!("[Error] {:?}", e);
                         }
                     },
-                    Err(_) => {}
+                    Ok(_)  => { },
+                    Err(e) => println!("[Error] {:?}", e)
                 }
             },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => { },
             Err(e) => println!("[Error] {:?}", e)
         };
     }
-    drop(socket);
 }
 
-pub fn run (host: &str, protocol: &str){
+/// The most peer IP addresses `udp_handler` remembers the route of.
+const MAX_ROUTES: usize = 1024;
+
+/// The address a socket bound to `local_socket_addr` receives `peer_socket_addr`'s
+/// requests on: when bound to any address (`0.0.0.0` or `::`), the one of the
+/// interface the system routes the responses through, for SOURCE-ADDRESS.
+/// The route is looked up once per peer IP address and kept in `routes`.
+fn route_local_addr(local_socket_addr: &SocketAddr, peer_socket_addr: &SocketAddr,
+    routes: &mut HashMap<IpAddr, IpAddr>) -> Result<SocketAddr, Error> {
+    if !local_socket_addr.ip().is_unspecified() {
+        return Ok(*local_socket_addr);
+    }
+    let ip = match routes.get(&peer_socket_addr.ip()) {
+        Some(ip) => *ip,
+        None => {
+            let socket = UdpSocket::bind(SocketAddr::new(local_socket_addr.ip(), 0))?;
+            socket.connect(peer_socket_addr)?;
+            let ip = socket.local_addr()?.ip();
+            if routes.len() >= MAX_ROUTES {
+                routes.clear();
+            }
+            routes.insert(peer_socket_addr.ip(), ip);
+            ip
+        }
+    };
+    Ok(SocketAddr::new(ip, local_socket_addr.port()))
+}
+
+/// Run the STUN server on `host` over `protocol` ("tcp" or "udp"), `alternate`
+/// is the UDP server's alternate address (see `udp_server`).
+pub fn run (host: &str, alternate: Option<&str>, protocol: &str){
     match protocol.to_lowercase().as_str() {
         "tcp" => tcp_server(host),
-        "udp" => udp_server(host),
+        "udp" => udp_server(host, alternate),
         _     => panic!("[Error] protocol error {:?}", protocol)
     }
-}
\ No newline at end of file
+}
@@ -1,22 +1,21 @@
 
-use std::str::FromStr;
-use std::string::ToString;
-use std::net::{ SocketAddr, lookup_host };
+use std::net::{ SocketAddr, IpAddr, ToSocketAddrs };
 
-use ::url::Url;
+use ::url::{Url, Host};
 
 use super::{STUN_PORT, STUNS_PORT};
+use super::error::Error;
 
 /// [RFC7064]:
 ///     https://tools.ietf.org/html/rfc7064
 ///     URI Scheme for the Session Traversal Utilities for NAT (STUN) Protocol
-
+///
 /// URI Scheme Syntax:
 ///     "stun" and "stuns" URIs have the following formal ABNF syntax
 ///     [RFC5234]:
 ///     stunURI       = scheme ":" host [ ":" port ]
 ///     scheme        = "stun" / "stuns"
-
+///
 /// Examples:
 ///     Table 1 shows examples for the "stun" and "stuns" URI schemes.  For
 ///     all these examples, the <host> component is populated with
@@ -28,31 +27,34 @@ use super::{STUN_PORT, STUNS_PORT};
 ///      | stuns:example.org     |
 ///      | stun:example.org:8000 |
 ///      +-----------------------+
+pub fn url_parse (s: &str) -> Result<SocketAddr, Error> {
+    parse(s, None)
+}
 
+/// Same as `url_parse`, with `port` instead of the scheme's default port when
+/// `s` has none, e.g. port 0 for a local address to bind.
+pub fn url_parse_with_default_port (s: &str, port: u16) -> Result<SocketAddr, Error> {
+    parse(s, Some(port))
+}
 
-pub fn url_parse (s: &str) -> Result<SocketAddr, &'static str> {
+fn parse (s: &str, default_port: Option<u16>) -> Result<SocketAddr, Error> {
     let mut uri = s.to_owned();
-    if uri.starts_with("stun") == false && uri.starts_with("stuns") == false {
+    if !uri.starts_with("stun") && !uri.starts_with("stuns") {
         uri = format!("stun://{}", uri);
     }
-    if uri.starts_with("stun:") && uri.starts_with("stun://") == false {
+    if uri.starts_with("stun:") && !uri.starts_with("stun://") {
         uri = uri.replace("stun:", "stun://");
-    } else if uri.starts_with("stun:") && uri.starts_with("stun://") == false {
+    } else if uri.starts_with("stuns:") && !uri.starts_with("stuns://") {
         uri = uri.replace("stuns:", "stuns://");
     }
     match Url::parse(uri.as_ref()) {
         Ok(url) => {
-            let scheme   = url.scheme();
-            let host_str = url.host_str(); // Option
-            let port     = url.port();     // Option
+            let scheme = url.scheme();
 
             if scheme != "stun" && scheme != "stuns" {
-                return Err("scheme error");
+                return Err(Error::InvalidUri(s.to_owned()));
             }
-            if host_str.is_none() {
-                return Err("host str error");
-            }
-            let port = match url.port() {
+            let port = match url.port().or(default_port) {
                 Some(port) => port,
                 None => match scheme {
                     "stun"  => STUN_PORT,
@@ -60,17 +62,17 @@ pub fn url_parse (s: &str) -> Result<SocketAddr, &'static str> {
                     _       => unreachable!()
                 }
             };
-            let mut loopup_host_iter = lookup_host(host_str.unwrap()).unwrap();
-            let socket_addr = match loopup_host_iter.next() {
-                Some(mut socket_addr) => {
-                    socket_addr.set_port(port);
-                    socket_addr
+            match url.host() {
+                Some(Host::Ipv4(ip)) => Ok(SocketAddr::new(IpAddr::V4(ip), port)),
+                Some(Host::Ipv6(ip)) => Ok(SocketAddr::new(IpAddr::V6(ip), port)),
+                Some(Host::Domain(domain)) => match (domain, port).to_socket_addrs()?.next() {
+                    Some(socket_addr) => Ok(socket_addr),
+                    None => Err(Error::InvalidUri(s.to_owned()))
                 },
-                None => return Err("lookup host failure.")
-            };
-            Ok(socket_addr)
+                None => Err(Error::InvalidUri(s.to_owned()))
+            }
         },
-        Err(_)  => Err("url parse error.")
+        Err(_)  => Err(Error::InvalidUri(s.to_owned()))
     }
 }
 
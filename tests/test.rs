@@ -0,0 +1,1335 @@
+extern crate ice;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, IpAddr, TcpListener, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use std::cell::Cell;
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ice::stun::{Error, server, client, demux};
+use ice::stun::framing::{StreamDecoder, FrameReader, Framing, write_frame};
+use ice::stun::packet::{Packet, PacketRef, MessageBuilder, ChannelData, Header, Class, Method, TransactionId, Attribute, AttributeType, Address, Family, ErrorCode, PasswordAlgorithm};
+use ice::stun::packet::{integrity, fingerprint};
+
+const BINDING_REQUEST: [u8; 20] = [
+    0, 1, 0, 0, 33, 18, 164, 66, 70, 149, 250,
+    122, 253, 177, 191, 174, 164, 118, 181, 61];
+
+#[test]
+fn packet_round_trip() {
+    let packet = Packet::from_bytes(&BINDING_REQUEST).unwrap();
+    assert_eq!(packet.attributes().len(), 0);
+    assert_eq!(packet.into_bytes().unwrap(), BINDING_REQUEST.to_vec());
+    assert_eq!(Packet::from_bytes(&packet.into_bytes().unwrap()).unwrap(), packet);
+}
+
+#[test]
+fn packet_length_mismatch() {
+    let mut bytes = BINDING_REQUEST.to_vec();
+    bytes.extend(&[0, 0, 0, 0]);
+    assert!(matches!(Packet::from_bytes(&bytes), Err(Error::LengthMismatch { declared: 0, actual: 4 })));
+
+    bytes[3] = 8;
+    assert!(matches!(Packet::from_bytes(&bytes), Err(Error::LengthMismatch { declared: 8, actual: 4 })));
+
+    bytes[3] = 2;
+    assert!(matches!(Packet::from_bytes(&bytes[..22]), Err(Error::UnalignedLength(2))));
+    assert!(matches!(Packet::from_bytes(&bytes[..12]), Err(Error::Truncated { offset: 12, needed: 8 })));
+}
+
+#[test]
+fn attribute_round_trip() {
+    let mut packet = Packet::from_bytes(&BINDING_REQUEST).unwrap();
+    packet.add_attribute(Attribute::MappedAddress("192.0.2.1:32853".parse().unwrap()));
+    packet.add_attribute(Attribute::OtherAddress("[2001:db8::1]:3478".parse().unwrap()));
+    packet.add_attribute(Attribute::UserName("evtj:h6vY".to_owned()));
+    packet.add_attribute(Attribute::LifeTime(600));
+    packet.add_attribute(Attribute::EvenPort(true));
+    packet.add_attribute(Attribute::Data(vec![1, 2, 3, 4, 5]));
+    packet.add_attribute(Attribute::Priority(0x6e0001ff));
+    packet.add_attribute(Attribute::ICEControlling(0x932ff9b151263b36));
+    packet.add_attribute(Attribute::ChangeRequest { change_ip: true, change_port: false });
+    packet.add_attribute(Attribute::DontFragment);
+    packet.add_attribute(Attribute::Software("ice".to_owned()));
+    packet.add_attribute(Attribute::Unknown { typ: 0x8fff, value: vec![0xff; 3] });
+
+    let bytes = packet.into_bytes().unwrap();
+    assert_eq!(bytes.len() % 4, 0);
+    assert_eq!(Packet::from_bytes(&bytes).unwrap(), packet);
+}
+
+#[test]
+fn xor_mapped_address() {
+    let header = Header::from_bytes(&[
+        0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xa4, 0x42,
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae]).unwrap();
+
+    let ipv4 = Attribute::XorMappedAddress("192.0.2.1:32853".parse().unwrap());
+    let bytes = ipv4.into_bytes(&header).unwrap();
+    assert_eq!(bytes, vec![0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+    assert_eq!(Attribute::from_bytes(AttributeType::XorMappedAddress, &bytes[4..], &header).unwrap(), ipv4);
+
+    let ipv6 = Attribute::XorPeerAddress("[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap());
+    let bytes = ipv6.into_bytes(&header).unwrap();
+    assert_eq!(&bytes[4..], &[
+        0x00, 0x02, 0xa1, 0x47, 0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79,
+        0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9][..]);
+    assert_eq!(Attribute::from_bytes(AttributeType::XorPeerAddress, &bytes[4..], &header).unwrap(), ipv6);
+}
+
+#[test]
+fn mapped_address_binary_layout() {
+    let header = Header::from_bytes(&BINDING_REQUEST).unwrap();
+
+    let ipv4 = Attribute::MappedAddress("192.0.2.1:32853".parse().unwrap());
+    assert_eq!(ipv4.into_bytes(&header).unwrap(), vec![0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x80, 0x55, 192, 0, 2, 1]);
+
+    let ipv6 = Attribute::AlternateServer("[2001:db8::1]:3478".parse().unwrap());
+    let bytes = ipv6.into_bytes(&header).unwrap();
+    assert_eq!(&bytes[..8], &[0x80, 0x23, 0x00, 0x14, 0x00, 0x02, 0x0d, 0x96][..]);
+    assert_eq!(bytes.len(), 24);
+
+    let address = Address::from_bytes(&bytes[4..]).unwrap();
+    assert_eq!(address.family(), &Family::Ipv6);
+    assert_eq!(address.to_socket_addr(), "[2001:db8::1]:3478".parse().unwrap());
+
+    assert!(Address::from_bytes(&[0x00, 0x01, 0x80, 0x55, 192, 0, 2]).is_err());
+    assert!(Address::from_bytes(&[0x00, 0x02, 0x80, 0x55, 192, 0, 2, 1]).is_err());
+    assert!(Address::from_bytes(&[0x00, 0x03, 0x80, 0x55, 192, 0, 2, 1]).is_err());
+}
+
+#[test]
+fn message_integrity() {
+    let key = integrity::long_term_key("user", "example.org", "secret").unwrap();
+    let mut packet = Packet::from_bytes(&BINDING_REQUEST).unwrap();
+    packet.add_attribute(Attribute::UserName("user".to_owned()));
+    packet.add_attribute(Attribute::Realm("example.org".to_owned()));
+
+    let bytes = packet.into_bytes_with_integrity(&key).unwrap();
+    assert_eq!(bytes.len(), 20 + 8 + 16 + 24);
+    let verified = Packet::verify(&bytes, &key).unwrap();
+    assert_eq!(&verified.attributes()[..2], packet.attributes());
+
+    let wrong_key = integrity::long_term_key("user", "example.org", "Secret").unwrap();
+    assert!(matches!(Packet::verify(&bytes, &wrong_key), Err(Error::IntegrityCheckFailure)));
+
+    let mut tampered = bytes.clone();
+    tampered[24] ^= 0x01;
+    assert!(matches!(Packet::verify(&tampered, &key), Err(Error::IntegrityCheckFailure)));
+
+    assert!(matches!(Packet::verify(&packet.into_bytes().unwrap(), &key), Err(Error::MissingIntegrity)));
+}
+
+#[test]
+fn message_integrity_sha256() {
+    let offered = vec![PasswordAlgorithm::Sha256, PasswordAlgorithm::Md5];
+    let algorithm = integrity::choose_password_algorithm(&offered).unwrap();
+    assert_eq!(algorithm, PasswordAlgorithm::Sha256);
+    let key = algorithm.key("user", "example.org", "secret").unwrap();
+
+    let mut packet = Packet::from_bytes(&BINDING_REQUEST).unwrap();
+    packet.add_attribute(Attribute::UserHash(integrity::user_hash("user", "example.org").unwrap()));
+    packet.add_attribute(Attribute::PasswordAlgorithms(offered.clone()));
+    packet.add_attribute(Attribute::PasswordAlgorithm(algorithm.clone()));
+
+    let bytes = packet.into_bytes_with_integrity_sha256(&key).unwrap();
+    let verified = Packet::verify_sha256(&bytes, &key).unwrap();
+    assert_eq!(&verified.attributes()[..3], packet.attributes());
+    assert_eq!(Packet::verify(&bytes, &key).unwrap(), verified);
+    assert_eq!(integrity::check_password_algorithm(&offered, &verified).unwrap(), PasswordAlgorithm::Sha256);
+
+    // bid-down: the attacker removed SHA-256 from the offered list
+    assert!(matches!(integrity::check_password_algorithm(&offered[1..], &verified),
+                     Err(Error::PasswordAlgorithmMismatch)));
+
+    let sha1_only = packet.into_bytes_with_integrity(&key).unwrap();
+    assert!(matches!(Packet::verify_sha256(&sha1_only, &key), Err(Error::MissingIntegrity)));
+    assert!(Packet::verify(&sha1_only, &key).is_ok());
+
+    let features = integrity::SECURITY_FEATURE_PASSWORD_ALGORITHMS;
+    let nonce = integrity::nonce_with_features(features, "f//499k954d6OL34");
+    assert!(nonce.starts_with(integrity::NONCE_COOKIE));
+    assert_eq!(integrity::nonce_features(&nonce), Some(features));
+    assert_eq!(integrity::nonce_features("f//499k954d6OL34"), None);
+}
+
+#[test]
+fn fingerprint() {
+    let key = integrity::short_term_key("secret").unwrap();
+    let mut packet = Packet::from_bytes(&BINDING_REQUEST).unwrap();
+    packet.add_attribute(Attribute::Software("ice".to_owned()));
+    packet.set_fingerprint(true);
+
+    let bytes = packet.into_bytes().unwrap();
+    assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], &[0x80, 0x28, 0x00, 0x04][..]);
+    assert!(fingerprint::check_fingerprint(&bytes));
+    assert_eq!(Packet::from_bytes(&bytes).unwrap(), packet);
+
+    let mut tampered = bytes.clone();
+    tampered[25] ^= 0x01;
+    assert!(!fingerprint::check_fingerprint(&tampered));
+    assert!(matches!(Packet::from_bytes(&tampered), Err(Error::FingerprintMismatch { .. })));
+
+    let signed = packet.into_bytes_with_integrity(&key).unwrap();
+    assert!(fingerprint::check_fingerprint(&signed));
+    let verified = Packet::verify(&signed, &key).unwrap();
+    assert!(verified.fingerprint());
+    assert_eq!(verified.into_bytes().unwrap(), signed);
+
+    packet.set_fingerprint(false);
+    assert!(!fingerprint::check_fingerprint(&packet.into_bytes().unwrap()));
+}
+
+#[test]
+fn decode_errors() {
+    let mut bytes = BINDING_REQUEST.to_vec();
+    bytes[3] = 8;
+    // LIFETIME with a 2 byte value
+    bytes.extend(&[0x00, 0x0d, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00]);
+    match Packet::from_bytes(&bytes) {
+        Err(ref e @ Error::Attribute { typ: 0x000d, offset: 20, .. }) => {
+            assert_eq!(e.error_code(), ErrorCode::BadRequest);
+        },
+        other => panic!("unexpected result: {:?}", other)
+    }
+
+    let header = Header::from_bytes(&BINDING_REQUEST).unwrap();
+    assert!(matches!(Attribute::from_bytes(AttributeType::LifeTime, &[0, 1], &header),
+                     Err(Error::InvalidLength { expected: 4, actual: 2 })));
+    assert!(matches!(Address::from_bytes(&[0x00, 0x03, 0x80, 0x55, 192, 0, 2, 1]),
+                     Err(Error::InvalidFamily(3))));
+
+    let mut classic = BINDING_REQUEST;
+    classic[4] = 0;
+    let header = Header::from_bytes(&classic).unwrap();
+    assert!(matches!(Attribute::from_bytes(AttributeType::XorMappedAddress, &[0, 1, 0, 0, 0, 0, 0, 0], &header),
+                     Err(Error::BadMagicCookie(0x0012a442))));
+
+    assert_eq!(Error::IntegrityCheckFailure.error_code(), ErrorCode::Unauthorized);
+}
+
+#[test]
+fn transaction_id() {
+    let header = Header::from_bytes(&BINDING_REQUEST).unwrap();
+    assert_eq!(header.transaction_id().as_bytes(), &BINDING_REQUEST[8..20]);
+    assert_eq!(header.transaction_id().to_string(), "4695fa7afdb1bfaea476b53d");
+    assert_eq!(header.into_bytes(), BINDING_REQUEST);
+
+    let mut outstanding = HashMap::new();
+    for _ in 0..16 {
+        let id = TransactionId::random().unwrap();
+        assert!(outstanding.insert(id, ()).is_none());
+    }
+
+    let id = TransactionId::random().unwrap();
+    let header = Header::new(Class::Request, Method::Binding, id);
+    assert_eq!(Header::from_bytes(&header.into_bytes()).unwrap(), header);
+    assert!(TransactionId::from_bytes(&BINDING_REQUEST[8..19]).is_err());
+
+    let classic = TransactionId::random_classic().unwrap();
+    assert_ne!(classic, TransactionId::random_classic().unwrap());
+    assert!(Header::new_classic(Class::Request, Method::Binding, classic).is_classic());
+}
+
+#[test]
+fn header_message_type() {
+    // RFC 5769 2.1 request and 2.2 response headers
+    let request = [0x00, 0x01, 0x00, 0x58, 0x21, 0x12, 0xa4, 0x42,
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae];
+    let header = Header::from_bytes(&request).unwrap();
+    assert_eq!(header.class(), &Class::Request);
+    assert_eq!(header.method(), &Method::Binding);
+    assert_eq!(header.length(), 0x58);
+    assert_eq!(header.into_bytes(), request);
+
+    let mut response = request;
+    response[0] = 0x01;
+    response[3] = 0x3c;
+    let header = Header::from_bytes(&response).unwrap();
+    assert_eq!(header.class(), &Class::SuccessResponse);
+    assert_eq!(header.method(), &Method::Binding);
+    assert_eq!(header.into_bytes(), response);
+
+    let types = [
+        (Class::Indication, Method::Binding, 0x0011),
+        (Class::FailureResponse, Method::Binding, 0x0111),
+        (Class::Request, Method::Allocate, 0x0003),
+        (Class::FailureResponse, Method::Refresh, 0x0114),
+        (Class::Indication, Method::Send, 0x0016),
+        (Class::SuccessResponse, Method::ConnectionAttempt, 0x010c)];
+    for &(ref class, ref method, message_type) in types.iter() {
+        let header = Header::new(class.clone(), method.clone(), TransactionId::new([7; 12]));
+        assert_eq!(header.message_type(), message_type);
+        assert_eq!(Header::from_bytes(&header.into_bytes()).unwrap(), header);
+    }
+
+    let mut invalid = request;
+    invalid[0] = 0x40;
+    assert!(matches!(Header::from_bytes(&invalid), Err(Error::InvalidMessageType(0x4001))));
+    assert!(matches!(Header::from_bytes(&request[..19]), Err(Error::Truncated { offset: 19, needed: 1 })));
+}
+
+// RFC 5769 Test Vectors for Session Traversal Utilities for NAT (STUN)
+// https://tools.ietf.org/html/rfc5769
+
+const RFC5769_PASSWORD: &str = "VOkJxbRl1RmTxUk/WvJxBt";
+
+const RFC5769_REQUEST: [u8; 108] = [
+    0x00, 0x01, 0x00, 0x58, 0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    0x80, 0x22, 0x00, 0x10, 0x53, 0x54, 0x55, 0x4e, 0x20, 0x74, 0x65, 0x73,
+    0x74, 0x20, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74,
+    0x00, 0x24, 0x00, 0x04, 0x6e, 0x00, 0x01, 0xff,
+    0x80, 0x29, 0x00, 0x08, 0x93, 0x2f, 0xf9, 0xb1, 0x51, 0x26, 0x3b, 0x36,
+    0x00, 0x06, 0x00, 0x09, 0x65, 0x76, 0x74, 0x6a, 0x3a, 0x68, 0x36, 0x76, 0x59, 0x20, 0x20, 0x20,
+    0x00, 0x08, 0x00, 0x14, 0x9a, 0xea, 0xa7, 0x0c, 0xbf, 0xd8, 0xcb, 0x56, 0x78, 0x1e,
+    0xf2, 0xb5, 0xb2, 0xd3, 0xf2, 0x49, 0xc1, 0xb5, 0x71, 0xa2,
+    0x80, 0x28, 0x00, 0x04, 0xe5, 0x7a, 0x3b, 0xcf];
+
+const RFC5769_IPV4_RESPONSE: [u8; 80] = [
+    0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20,
+    0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43,
+    0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99, 0xfd, 0x9e, 0x90, 0xc3,
+    0x8c, 0x74, 0x89, 0xf9, 0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7,
+    0x80, 0x28, 0x00, 0x04, 0xc0, 0x7d, 0x4c, 0x96];
+
+const RFC5769_IPV6_RESPONSE: [u8; 92] = [
+    0x01, 0x01, 0x00, 0x48, 0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20,
+    0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xa1, 0x47,
+    0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+    0x00, 0x08, 0x00, 0x14, 0xa3, 0x82, 0x95, 0x4e, 0x4b, 0xe6, 0x7b, 0xf1,
+    0x17, 0x84, 0xc9, 0x7c, 0x82, 0x92, 0xc2, 0x75, 0xbf, 0xe3, 0xed, 0x41,
+    0x80, 0x28, 0x00, 0x04, 0xc8, 0xfb, 0x0b, 0x4c];
+
+const RFC5769_LONG_TERM_REQUEST: [u8; 116] = [
+    0x00, 0x01, 0x00, 0x60, 0x21, 0x12, 0xa4, 0x42,
+    0x78, 0xad, 0x34, 0x33, 0xc6, 0xad, 0x72, 0xc0, 0x29, 0xda, 0x41, 0x2e,
+    0x00, 0x06, 0x00, 0x12, 0xe3, 0x83, 0x9e, 0xe3, 0x83, 0x88, 0xe3, 0x83,
+    0xaa, 0xe3, 0x83, 0x83, 0xe3, 0x82, 0xaf, 0xe3, 0x82, 0xb9, 0x00, 0x00,
+    0x00, 0x15, 0x00, 0x1c, 0x66, 0x2f, 0x2f, 0x34, 0x39, 0x39, 0x6b, 0x39,
+    0x35, 0x34, 0x64, 0x36, 0x4f, 0x4c, 0x33, 0x34, 0x6f, 0x4c, 0x39, 0x46,
+    0x53, 0x54, 0x76, 0x79, 0x36, 0x34, 0x73, 0x41,
+    0x00, 0x14, 0x00, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x6f, 0x72, 0x67, 0x00,
+    0x00, 0x08, 0x00, 0x14, 0xf6, 0x70, 0x24, 0x65, 0x6d, 0xd6, 0x4a, 0x3e,
+    0x02, 0xb8, 0xe0, 0x71, 0x2e, 0x85, 0xc9, 0xa2, 0x8c, 0xa8, 0x96, 0x66];
+
+/// Decode `bytes`, check MESSAGE-INTEGRITY and FINGERPRINT, re-encode and compare.
+fn rfc5769_round_trip(bytes: &[u8], key: &[u8]) -> Packet {
+    let packet = Packet::verify(bytes, key).unwrap();
+    assert_eq!(packet.into_bytes_with_integrity(key).unwrap(), bytes.to_vec());
+    assert_eq!(Packet::from_bytes(bytes).unwrap().into_bytes().unwrap(), bytes.to_vec());
+    packet
+}
+
+#[test]
+fn rfc5769_sample_request() {
+    let key = integrity::short_term_key(RFC5769_PASSWORD).unwrap();
+    assert!(fingerprint::check_fingerprint(&RFC5769_REQUEST));
+
+    let packet = rfc5769_round_trip(&RFC5769_REQUEST, &key);
+    assert_eq!(packet.header().class(), &Class::Request);
+    assert_eq!(packet.header().method(), &Method::Binding);
+    assert!(packet.fingerprint());
+    assert_eq!(packet.padding(), 0x20);
+    assert_eq!(&packet.attributes()[..4], &[
+        Attribute::Software("STUN test client".to_owned()),
+        Attribute::Priority(0x6e0001ff),
+        Attribute::ICEControlled(0x932ff9b151263b36),
+        Attribute::UserName("evtj:h6vY".to_owned())][..]);
+
+    let wrong_key = integrity::short_term_key("VOkJxbRl1RmTxUk/WvJxBT").unwrap();
+    assert!(matches!(Packet::verify(&RFC5769_REQUEST, &wrong_key), Err(Error::IntegrityCheckFailure)));
+}
+
+#[test]
+fn rfc5769_sample_ipv4_response() {
+    let key = integrity::short_term_key(RFC5769_PASSWORD).unwrap();
+    assert!(fingerprint::check_fingerprint(&RFC5769_IPV4_RESPONSE));
+
+    let packet = rfc5769_round_trip(&RFC5769_IPV4_RESPONSE, &key);
+    assert_eq!(packet.header().class(), &Class::SuccessResponse);
+    assert_eq!(&packet.attributes()[..2], &[
+        Attribute::Software("test vector".to_owned()),
+        Attribute::XorMappedAddress("192.0.2.1:32853".parse().unwrap())][..]);
+}
+
+#[test]
+fn rfc5769_sample_ipv6_response() {
+    let key = integrity::short_term_key(RFC5769_PASSWORD).unwrap();
+    assert!(fingerprint::check_fingerprint(&RFC5769_IPV6_RESPONSE));
+
+    let packet = rfc5769_round_trip(&RFC5769_IPV6_RESPONSE, &key);
+    assert_eq!(packet.header().class(), &Class::SuccessResponse);
+    assert_eq!(&packet.attributes()[..2], &[
+        Attribute::Software("test vector".to_owned()),
+        Attribute::XorMappedAddress("[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap())][..]);
+}
+
+#[test]
+fn rfc5769_sample_long_term_request() {
+    // the password is "TheMatrIX" after SASLprep
+    let username = "\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}";
+    let key = integrity::long_term_key(username, "example.org", "The\u{00AD}M\u{00AA}tr\u{2168}").unwrap();
+    assert_eq!(key, integrity::long_term_key(username, "example.org", "TheMatrIX").unwrap());
+    assert!(!fingerprint::check_fingerprint(&RFC5769_LONG_TERM_REQUEST));
+
+    let packet = rfc5769_round_trip(&RFC5769_LONG_TERM_REQUEST, &key);
+    assert!(!packet.fingerprint());
+    assert_eq!(packet.padding(), 0x00);
+    assert_eq!(&packet.attributes()[..3], &[
+        Attribute::UserName("\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}".to_owned()),
+        Attribute::Nonce("f//499k954d6OL34oL9FSTvy64sA".to_owned()),
+        Attribute::Realm("example.org".to_owned())][..]);
+}
+
+#[test]
+fn packet_ref() {
+    let packet = PacketRef::new(&RFC5769_REQUEST).unwrap();
+    assert_eq!(packet.message_type(), 0x0001);
+    assert_eq!(packet.length(), 0x58);
+    assert_eq!(packet.transaction_id().as_bytes(), &RFC5769_REQUEST[8..20]);
+    assert_eq!(packet.header().unwrap().method(), &Method::Binding);
+
+    let types: Vec<AttributeType> = packet.attributes().map(|attribute| attribute.unwrap().0).collect();
+    assert_eq!(types, vec![
+        AttributeType::Software, AttributeType::Priority, AttributeType::ICEControlled,
+        AttributeType::UserName, AttributeType::MessageIntegrity, AttributeType::FingerPrint]);
+    assert_eq!(packet.attribute(AttributeType::UserName), Some(&b"evtj:h6vY"[..]));
+    assert_eq!(packet.attribute(AttributeType::Realm), None);
+    assert_eq!(packet.to_packet().unwrap(), Packet::from_bytes(&RFC5769_REQUEST).unwrap());
+
+    let mut unknown = BINDING_REQUEST.to_vec();
+    unknown[3] = 16;
+    unknown.extend(&[0x7f, 0xff, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00]);
+    unknown.extend(&[0x80, 0x22, 0x00, 0x03, 0x69, 0x63, 0x65, 0x00]);
+    let packet = PacketRef::new(&unknown).unwrap();
+    let mut attributes = packet.attributes();
+    assert!(matches!(attributes.next(), Some(Err(Error::UnknownAttribute(0x7fff)))));
+    assert_eq!(attributes.next().unwrap().unwrap(), (AttributeType::Software, &b"ice"[..]));
+    assert!(attributes.next().is_none());
+    assert_eq!(packet.raw_attributes().next().unwrap().unwrap(), (0x7fff, &[1][..]));
+
+    // the attribute claims more bytes than the message holds
+    unknown[23] = 0x10;
+    let packet = PacketRef::new(&unknown).unwrap();
+    assert!(matches!(packet.attributes().next(), Some(Err(Error::Truncated { .. }))));
+    assert_eq!(packet.attribute(AttributeType::Software), None);
+
+    assert!(matches!(PacketRef::new(&unknown[..24]), Err(Error::LengthMismatch { declared: 16, actual: 4 })));
+    assert!(matches!(PacketRef::new(&[0xc0; 20]), Err(Error::InvalidMessageType(0xc0c0))));
+}
+
+#[test]
+fn message_builder() {
+    let key = integrity::short_term_key(RFC5769_PASSWORD).unwrap();
+    let request = Packet::from_bytes(&RFC5769_IPV4_RESPONSE).unwrap();
+
+    // RFC 5769 2.2 pads with spaces, the builder pads with zeroes
+    let mut buffer = [0u8; 128];
+    let size = MessageBuilder::response(&mut buffer, request.header(), Class::SuccessResponse).unwrap()
+        .attribute(&Attribute::Software("test vector".to_owned())).unwrap()
+        .attribute(&Attribute::XorMappedAddress("192.0.2.1:32853".parse().unwrap())).unwrap()
+        .fingerprint(true)
+        .finish_with_integrity(&key).unwrap();
+    assert_eq!(size, RFC5769_IPV4_RESPONSE.len());
+    let mut packet = request.clone();
+    packet.set_padding(0);
+    assert_eq!(&buffer[..size], &packet.into_bytes_with_integrity(&key).unwrap()[..]);
+    assert!(Packet::verify(&buffer[..size], &key).is_ok());
+
+    let id = TransactionId::new([1; 12]);
+    let size = MessageBuilder::new(&mut buffer, Class::Request, Method::Binding, id).unwrap()
+        .raw_attribute(0x8022, b"ice").unwrap()
+        .finish_with_integrity_sha256(&key).unwrap();
+    let packet = Packet::verify_sha256(&buffer[..size], &key).unwrap();
+    assert_eq!(packet.header().transaction_id(), &id);
+    assert_eq!(packet.attributes()[0], Attribute::Software("ice".to_owned()));
+
+    let size = MessageBuilder::new(&mut buffer, Class::Indication, Method::Binding, id).unwrap().finish().unwrap();
+    assert_eq!(size, 20);
+    assert_eq!(&buffer[..4], &[0x00, 0x11, 0x00, 0x00]);
+
+    let mut small = [0u8; 24];
+    let result = MessageBuilder::new(&mut small, Class::Request, Method::Binding, id).unwrap()
+        .attribute(&Attribute::Software("ice".to_owned()));
+    assert!(matches!(result, Err(Error::BufferTooSmall { capacity: 24, needed: 28 })));
+    assert!(matches!(MessageBuilder::new(&mut small[..19], Class::Request, Method::Binding, id),
+                     Err(Error::BufferTooSmall { capacity: 19, needed: 20 })));
+
+    // the 16 bits length fields cannot describe more than 65535 bytes
+    let mut large = vec![0u8; 90000];
+    let result = MessageBuilder::new(&mut large, Class::Indication, Method::Send, id).unwrap()
+        .attribute(&Attribute::Data(vec![0; 70000]));
+    assert!(matches!(result, Err(Error::MessageTooLarge { max: 65535, actual: 70004 })));
+    let result = MessageBuilder::new(&mut large, Class::Indication, Method::Send, id).unwrap()
+        .attribute(&Attribute::Data(vec![0; 40000])).unwrap()
+        .attribute(&Attribute::Data(vec![0; 40000]));
+    assert!(matches!(result, Err(Error::MessageTooLarge { max: 65535, actual: 80008 })));
+    let mut packet = Packet::new(Header::new(Class::Indication, Method::Send, id)).unwrap();
+    packet.add_attribute(Attribute::Data(vec![0; 65528]));
+    assert_eq!(packet.into_bytes().unwrap().len(), 20 + 65532);
+    packet.set_fingerprint(true);
+    assert!(matches!(packet.into_bytes(), Err(Error::MessageTooLarge { max: 65535, actual: 65540 })));
+    assert!(matches!(Attribute::Data(vec![0; 70000]).into_bytes(packet.header()),
+                     Err(Error::MessageTooLarge { actual: 70000, .. })));
+
+    let software = Attribute::Software("ice".to_owned());
+    let mut value = [0xFFu8; 8];
+    assert_eq!(software.encode(packet.header(), 0x20, &mut value).unwrap(), 8);
+    assert_eq!(&value, &[0x80, 0x22, 0x00, 0x03, b'i', b'c', b'e', 0x20]);
+    assert!(matches!(software.encode(packet.header(), 0, &mut value[..7]),
+                     Err(Error::BufferTooSmall { capacity: 7, needed: 8 })));
+}
+
+#[test]
+fn unknown_comprehension_required() {
+    let mut bytes = BINDING_REQUEST.to_vec();
+    bytes.extend(&[0x7f, 0xff, 0x00, 0x00]);
+    bytes.extend(&[0x8f, 0xff, 0x00, 0x02, 0x01, 0x02, 0x00, 0x00]);
+    bytes.extend(&[0x00, 0x31, 0x00, 0x04, 0x01, 0x02, 0x03, 0x04]);
+    bytes.extend(&[0x00, 0x24, 0x00, 0x04, 0x6e, 0x00, 0x01, 0xff]);
+    bytes[3] = 28;
+    let packet = Packet::from_bytes(&bytes).unwrap();
+    assert_eq!(packet.unknown_comprehension_required(), vec![0x7fff, 0x0031]);
+    assert!(!packet.attributes()[1].is_comprehension_required());
+    assert!(packet.attributes()[3].is_comprehension_required());
+
+    let peer  = "192.0.2.1:32853".parse().unwrap();
+    let local = "192.0.2.2:3478".parse().unwrap();
+    let mut response = [0u8; 548];
+    let size = server::handler(&bytes, &mut response, &peer, &local).unwrap();
+    let error = Packet::from_bytes(&response[..size]).unwrap();
+    assert_eq!(error.header().class(), &Class::FailureResponse);
+    assert_eq!(error.header().transaction_id(), packet.header().transaction_id());
+    assert_eq!(error.attributes(), &[
+        Attribute::ErrorCode(ErrorCode::UnknownAttribute, "Unknown attribute(s)".to_owned()),
+        Attribute::UnknownAttribute(vec![0x7fff, 0x0031])][..]);
+
+    let size = server::handler(&BINDING_REQUEST, &mut response, &peer, &local).unwrap();
+    let success = Packet::from_bytes(&response[..size]).unwrap();
+    assert_eq!(success.header().class(), &Class::SuccessResponse);
+    assert_eq!(success.attributes(), &[Attribute::XorMappedAddress(peer)][..]);
+
+    // an indication with unknown comprehension-required attributes is discarded
+    bytes[1] = 0x11;
+    assert_eq!(server::handler(&bytes, &mut response, &peer, &local).unwrap(), 0);
+}
+
+#[test]
+fn server_answers_binding_only() {
+    let peer  = "192.0.2.1:32853".parse().unwrap();
+    let local = "192.0.2.2:3478".parse().unwrap();
+    let mut request  = [0u8; 548];
+    let mut response = [0u8; 548];
+    let size = MessageBuilder::new(&mut request, Class::Request, Method::Allocate, TransactionId::new([1; 12])).unwrap()
+        .finish().unwrap();
+    let size = server::handler(&request[..size], &mut response, &peer, &local).unwrap();
+    let error = Packet::from_bytes(&response[..size]).unwrap();
+    assert_eq!(error.header().class(), &Class::FailureResponse);
+    assert_eq!(error.header().method(), &Method::Allocate);
+    assert_eq!(error.error_code(), Some((&ErrorCode::BadRequest, "Bad request")));
+}
+
+#[test]
+fn error_code_attribute() {
+    let header = Header::from_bytes(&BINDING_REQUEST).unwrap();
+
+    let stale = Attribute::ErrorCode(ErrorCode::StaleNonce, "Nonce expired".to_owned());
+    let bytes = stale.into_bytes(&header).unwrap();
+    assert_eq!(&bytes[..8], &[0x00, 0x09, 0x00, 0x11, 0x00, 0x00, 0x04, 38][..]);
+    assert_eq!(&bytes[8..21], &b"Nonce expired"[..]);
+    assert_eq!(bytes.len(), 24);
+    assert_eq!(Attribute::from_bytes(AttributeType::ErrorCode, &bytes[4..21], &header).unwrap(), stale);
+
+    // unassigned codes are kept with their reason phrase
+    let value = [0x00, 0x00, 0x04, 99, b'N', b'o', b'p', b'e'];
+    let other = Attribute::from_bytes(AttributeType::ErrorCode, &value, &header).unwrap();
+    assert_eq!(other, Attribute::ErrorCode(ErrorCode::Other(499, "Nope".to_owned()), "Nope".to_owned()));
+    assert_eq!(&other.into_bytes(&header).unwrap()[4..12], &value[..]);
+
+    assert!(matches!(Attribute::from_bytes(AttributeType::ErrorCode, &[0, 0, 7, 0], &header),
+                     Err(Error::InvalidErrorCode(700))));
+    assert!(matches!(Attribute::from_bytes(AttributeType::ErrorCode, &[0, 0, 4, 100], &header),
+                     Err(Error::InvalidErrorCode(500))));
+    assert!(matches!(Attribute::from_bytes(AttributeType::ErrorCode, &[0, 0, 2, 0], &header),
+                     Err(Error::InvalidErrorCode(200))));
+
+    let mut response = Packet::new(Header::new(Class::FailureResponse, Method::Binding, TransactionId::new([1; 12]))).unwrap();
+    response.add_attribute(Attribute::from(ErrorCode::Unauthorized));
+    let response = Packet::from_bytes(&response.into_bytes().unwrap()).unwrap();
+    assert_eq!(response.error_code(), Some((&ErrorCode::Unauthorized, "Unauthorized")));
+}
+
+#[test]
+fn classic_stun() {
+    let transaction_id = [0x5a; 16];
+    let header = Header::new_classic(Class::Request, Method::Binding, transaction_id);
+    assert!(header.is_classic());
+    assert_eq!(&header.into_bytes()[4..], &transaction_id[..]);
+    assert!(!Header::from_bytes(&BINDING_REQUEST).unwrap().is_classic());
+
+    let peer      = "192.0.2.1:32853".parse().unwrap();
+    let local     = "192.0.2.2:3478".parse().unwrap();
+    let alternate = "192.0.2.3:3479".parse().unwrap();
+    let mut response = [0u8; 548];
+
+    let request = Packet::new(header.clone()).unwrap();
+    let reply = server::handle(&request.into_bytes().unwrap(), &mut response, &peer, &local, Some(&alternate)).unwrap();
+    assert_eq!(reply.destination, peer);
+    let packet = Packet::from_bytes(&response[..reply.size]).unwrap();
+    assert_eq!(packet.header(), &Header::from_bytes(&{
+        let mut bytes = header.into_bytes();
+        bytes[0] = 0x01;
+        bytes[3] = 36;
+        bytes
+    }).unwrap());
+    assert_eq!(packet.attributes(), &[
+        Attribute::MappedAddress(peer),
+        Attribute::SourceAddress(local),
+        Attribute::ChangedAddress(alternate)][..]);
+
+    // CHANGE-REQUEST: the response comes from the alternate address
+    let mut request = Packet::new(header.clone()).unwrap();
+    request.add_attribute(Attribute::ChangeRequest { change_ip: true, change_port: false });
+    let reply = server::handle(&request.into_bytes().unwrap(), &mut response, &peer, &local, Some(&alternate)).unwrap();
+    assert_eq!(reply.source, "192.0.2.3:3478".parse().unwrap());
+    let packet = Packet::from_bytes(&response[..reply.size]).unwrap();
+    assert_eq!(packet.attributes()[1], Attribute::SourceAddress(reply.source));
+
+    // without an alternate address CHANGE-REQUEST can't be honoured
+    let reply = server::handle(&request.into_bytes().unwrap(), &mut response, &peer, &local, None).unwrap();
+    let packet = Packet::from_bytes(&response[..reply.size]).unwrap();
+    assert_eq!(packet.header().class(), &Class::FailureResponse);
+    // RFC 3489 pads UNKNOWN-ATTRIBUTES by repeating an attribute
+    assert_eq!(packet.attributes()[1], Attribute::UnknownAttribute(vec![0x0003, 0x0003]));
+
+    // RESPONSE-ADDRESS is honoured for the request's own IP address only
+    let mut request = Packet::new(header.clone()).unwrap();
+    request.add_attribute(Attribute::ResponseAddress("192.0.2.1:40000".parse().unwrap()));
+    let reply = server::handle(&request.into_bytes().unwrap(), &mut response, &peer, &local, None).unwrap();
+    assert_eq!(reply.destination, "192.0.2.1:40000".parse().unwrap());
+    let packet = Packet::from_bytes(&response[..reply.size]).unwrap();
+    // no CHANGED-ADDRESS without an alternate address
+    assert_eq!(packet.attributes(), &[
+        Attribute::MappedAddress(peer),
+        Attribute::SourceAddress(local),
+        Attribute::ReflectedFrom(peer)][..]);
+
+    let mut request = Packet::new(header).unwrap();
+    request.add_attribute(Attribute::ResponseAddress("198.51.100.1:40000".parse().unwrap()));
+    let reply = server::handle(&request.into_bytes().unwrap(), &mut response, &peer, &local, None).unwrap();
+    assert_eq!(reply.destination, peer);
+    assert_eq!(Packet::from_bytes(&response[..reply.size]).unwrap().attributes().len(), 2);
+}
+
+#[test]
+fn string_preparation() {
+    assert_eq!(integrity::saslprep("I\u{00AD}X").unwrap(), "IX");
+    assert_eq!(integrity::saslprep("user").unwrap(), "user");
+    assert_eq!(integrity::saslprep("\u{00AA}").unwrap(), "a");
+    assert_eq!(integrity::saslprep("\u{2168}").unwrap(), "IX");
+    assert!(matches!(integrity::saslprep("\u{0007}"), Err(Error::StringPrep)));
+
+    // OpaqueString keeps compatibility characters, maps spaces and composes
+    assert_eq!(integrity::opaque_string("\u{2168}").unwrap(), "\u{2168}");
+    assert_eq!(integrity::opaque_string("a\u{00A0}b").unwrap(), "a b");
+    assert_eq!(integrity::opaque_string("e\u{0301}").unwrap(), "\u{00E9}");
+    assert!(matches!(integrity::opaque_string(""), Err(Error::StringPrep)));
+    assert!(matches!(integrity::opaque_string("a\u{0000}"), Err(Error::StringPrep)));
+
+    let md5 = PasswordAlgorithm::Md5;
+    assert_eq!(md5.key("user", "example.org", "e\u{0301}").unwrap(), md5.key("user", "example.org", "\u{00E9}").unwrap());
+    assert!(PasswordAlgorithm::Other { number: 3, parameters: vec![] }.key("user", "example.org", "secret").is_err());
+
+    // the username and realm are prepared too, both derivations agree on them
+    let username = "\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}";
+    let key = integrity::long_term_key(username, "e\u{0301}xample.org", "secret").unwrap();
+    assert_eq!(key, integrity::long_term_key(username, "\u{00E9}xample.org", "secret").unwrap());
+    assert_eq!(key, md5.key(username, "e\u{0301}xample.org", "secret").unwrap());
+    assert_eq!(key, md5.key(username, "\u{00E9}xample.org", "secret").unwrap());
+
+    let header = Header::from_bytes(&BINDING_REQUEST).unwrap();
+    let username = Attribute::UserName("x".repeat(513));
+    assert!(matches!(username.into_bytes(&header), Err(Error::TooLong { max: 512, actual: 513 })));
+    // decoding is tolerant, an over-long string attribute doesn't make the message undecodable
+    assert_eq!(Attribute::from_bytes(AttributeType::UserName, "x".repeat(513).as_bytes(), &header).unwrap(), username);
+    let mut bytes = BINDING_REQUEST.to_vec();
+    bytes.extend(&[0x80, 0x22, 0x00, 0xc8]);
+    bytes.extend(&[b'x'; 200][..]);
+    bytes[3] = 204;
+    let packet = Packet::from_bytes(&bytes).unwrap();
+    assert_eq!(packet.attributes(), &[Attribute::Software("x".repeat(200))][..]);
+    assert!(matches!(packet.into_bytes(), Err(Error::TooLong { .. })));
+    assert!(Attribute::UserName("x".repeat(512)).check_limits().is_ok());
+
+    // 127 characters of 3 bytes each are fine, 128 characters are not
+    assert!(Attribute::Realm("\u{30DE}".repeat(127)).check_limits().is_ok());
+    assert!(matches!(Attribute::Software("\u{30DE}".repeat(128)).check_limits(),
+                     Err(Error::TooLong { max: 127, actual: 128 })));
+    assert!(matches!(Attribute::Nonce("x".repeat(128)).check_limits(), Err(Error::TooLong { .. })));
+
+    let mut buffer = [0u8; 1024];
+    let result = MessageBuilder::new(&mut buffer, Class::Request, Method::Binding, TransactionId::new([0; 12])).unwrap()
+        .attribute(&Attribute::Software("x".repeat(200)));
+    assert!(matches!(result, Err(Error::TooLong { .. })));
+}
+
+#[test]
+fn channel_data() {
+    let data = [0xde, 0xad, 0xbe, 0xef, 0x01];
+    let message = ChannelData::new(0x4001, &data).unwrap();
+    let bytes = message.into_bytes();
+    assert_eq!(bytes, vec![0x40, 0x01, 0x00, 0x05, 0xde, 0xad, 0xbe, 0xef, 0x01]);
+    assert_eq!(ChannelData::from_bytes(&bytes).unwrap(), message);
+
+    let padded = message.into_bytes_padded();
+    assert_eq!(padded.len(), 12);
+    let decoded = ChannelData::from_bytes(&padded).unwrap();
+    assert_eq!(decoded.channel(), 0x4001);
+    assert_eq!(decoded.data(), &data[..]);
+
+    assert!(matches!(ChannelData::new(0x3fff, &data), Err(Error::InvalidChannelNumber(0x3fff))));
+    assert!(matches!(ChannelData::from_bytes(&[0x80, 0x00, 0x00, 0x00]), Err(Error::InvalidChannelNumber(0x8000))));
+    assert!(matches!(ChannelData::from_bytes(&bytes[..8]), Err(Error::Truncated { offset: 8, needed: 1 })));
+    assert!(matches!(ChannelData::from_bytes(&bytes[..3]), Err(Error::Truncated { offset: 3, needed: 1 })));
+}
+
+#[test]
+fn demultiplexing() {
+    assert_eq!(demux::classify(&RFC5769_REQUEST), demux::Protocol::Stun);
+    assert_eq!(demux::classify(&[0x40, 0x01, 0x00, 0x00]), demux::Protocol::ChannelData);
+    assert_eq!(demux::classify(&[0x4f]), demux::Protocol::ChannelData);
+    assert_eq!(demux::classify(&[0x16, 0xfe, 0xfd]), demux::Protocol::Dtls);
+    assert_eq!(demux::classify(&[0x14]), demux::Protocol::Dtls);
+    assert_eq!(demux::classify(&[0x10]), demux::Protocol::Zrtp);
+    assert_eq!(demux::classify(&[0x80, 0x60]), demux::Protocol::Rtp);
+    assert_eq!(demux::classify(&[0xbf]), demux::Protocol::Rtp);
+    assert_eq!(demux::classify(&[0x04]), demux::Protocol::Unknown);
+    assert_eq!(demux::classify(&[0x50]), demux::Protocol::Unknown);
+    assert_eq!(demux::classify(&[0xc0]), demux::Protocol::Unknown);
+    assert_eq!(demux::classify(&[]), demux::Protocol::Unknown);
+}
+
+/// A stream handing out its bytes in chunks of the given sizes, in turn.
+struct ChunkedStream {
+    bytes : Vec<u8>,
+    chunks: Vec<usize>,
+    offset: usize,
+    reads : usize
+}
+
+impl Read for ChunkedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk = self.chunks[self.reads % self.chunks.len()];
+        let size  = chunk.min(buf.len()).min(self.bytes.len() - self.offset);
+        buf[..size].copy_from_slice(&self.bytes[self.offset..self.offset + size]);
+        self.offset += size;
+        self.reads  += 1;
+        Ok(size)
+    }
+}
+
+#[test]
+fn stream_framing() {
+    let data = [1, 2, 3, 4, 5];
+    let channel_data = ChannelData::new(0x4000, &data).unwrap().into_bytes();
+    let mut stream = Vec::new();
+    write_frame(&mut stream, Framing::Native, &BINDING_REQUEST).unwrap();
+    write_frame(&mut stream, Framing::Native, &channel_data).unwrap();
+    write_frame(&mut stream, Framing::Native, &RFC5769_REQUEST).unwrap();
+    assert_eq!(stream.len(), 20 + 12 + RFC5769_REQUEST.len());
+
+    for chunks in [vec![1], vec![7, 3], vec![stream.len()]] {
+        let mut reader = FrameReader::new(ChunkedStream { bytes: stream.clone(), chunks, offset: 0, reads: 0 }, Framing::Native);
+        assert_eq!(reader.read_frame().unwrap().unwrap(), BINDING_REQUEST.to_vec());
+        assert_eq!(reader.read_frame().unwrap().unwrap(), channel_data);
+        assert_eq!(reader.read_frame().unwrap().unwrap(), RFC5769_REQUEST.to_vec());
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    let mut stream = Vec::new();
+    write_frame(&mut stream, Framing::Rfc4571, &BINDING_REQUEST).unwrap();
+    write_frame(&mut stream, Framing::Rfc4571, &channel_data).unwrap();
+    assert_eq!(&stream[..2], &[0, 20]);
+    let mut decoder = StreamDecoder::new(Framing::Rfc4571);
+    decoder.extend(&stream[..10]);
+    assert!(decoder.next_frame().unwrap().is_none());
+    assert_eq!(decoder.missing(), 12);
+    decoder.extend(&stream[10..]);
+    assert_eq!(decoder.next_frame().unwrap().unwrap(), BINDING_REQUEST.to_vec());
+    assert_eq!(decoder.next_frame().unwrap().unwrap(), channel_data);
+    assert_eq!(decoder.pending(), 0);
+
+    let mut reader = FrameReader::new(&BINDING_REQUEST[..12], Framing::Native);
+    assert!(matches!(reader.read_frame(), Err(Error::Truncated { offset: 12, needed: 8 })));
+    let mut reader = FrameReader::new(&[0x80, 0x01, 0x00, 0x00][..], Framing::Native);
+    assert!(matches!(reader.read_frame(), Err(Error::InvalidMessageType(0x8001))));
+}
+
+#[test]
+fn tcp_server_many_messages() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let local_socket_addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        server::tcp_handler(stream, &local_socket_addr).unwrap();
+    });
+
+    let mut stream = TcpStream::connect(local_socket_addr).unwrap();
+    let mut requests = BINDING_REQUEST.to_vec();
+    requests.extend(ChannelData::new(0x4000, &[1]).unwrap().into_bytes_padded());
+    requests.extend(RFC5769_REQUEST.iter());
+    stream.write_all(&requests).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+    let mut reader = FrameReader::new(stream, Framing::Native);
+    let mut transaction_ids = Vec::new();
+    while let Some(frame) = reader.read_frame().unwrap() {
+        let response = Packet::from_bytes(&frame).unwrap();
+        assert_eq!(response.header().class(), &Class::SuccessResponse);
+        transaction_ids.push(*response.header().transaction_id());
+    }
+    assert_eq!(transaction_ids, vec![
+        *Packet::from_bytes(&BINDING_REQUEST).unwrap().header().transaction_id(),
+        *Packet::from_bytes(&RFC5769_REQUEST).unwrap().header().transaction_id()]);
+    server.join().unwrap();
+}
+
+#[test]
+fn udp_server_sockets() {
+    // bound to any address, SOURCE-ADDRESS is the one the request came in on
+    let stop = Arc::new(AtomicBool::new(false));
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    let any = socket.local_addr().unwrap();
+    let sockets: HashMap<SocketAddr, UdpSocket> = vec![(any, socket)].into_iter().collect();
+    let any_handler = {
+        let stop = stop.clone();
+        thread::spawn(move || server::udp_handler(&sockets, &any, None, &stop))
+    };
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut request = [0u8; 64];
+    let size = MessageBuilder::with_header(&mut request, Header::new_classic(Class::Request, Method::Binding, [1; 16])).unwrap()
+        .finish().unwrap();
+    client.send_to(&request[..size], ("127.0.0.1", any.port())).unwrap();
+    let mut buf = [0u8; 512];
+    let (size, _) = client.recv_from(&mut buf).unwrap();
+    let response = Packet::from_bytes(&buf[..size]).unwrap();
+    assert_eq!(response.attributes(), &[
+        Attribute::MappedAddress(client.local_addr().unwrap()),
+        Attribute::SourceAddress(SocketAddr::new("127.0.0.1".parse().unwrap(), any.port()))][..]);
+
+    // CHANGE-REQUEST is answered from the socket bound to the alternate port
+    let primary   = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let alternate = UdpSocket::bind("127.0.0.1:0").unwrap();
+    primary.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    let (primary_addr, alternate_addr) = (primary.local_addr().unwrap(), alternate.local_addr().unwrap());
+    let sockets: HashMap<SocketAddr, UdpSocket> = vec![(primary_addr, primary), (alternate_addr, alternate)].into_iter().collect();
+    let primary_handler = {
+        let stop = stop.clone();
+        thread::spawn(move || server::udp_handler(&sockets, &primary_addr, Some(&alternate_addr), &stop))
+    };
+
+    let size = MessageBuilder::new(&mut request, Class::Request, Method::Binding, TransactionId::new([2; 12])).unwrap()
+        .attribute(&Attribute::ChangeRequest { change_ip: false, change_port: true }).unwrap()
+        .finish().unwrap();
+    client.send_to(&request[..size], primary_addr).unwrap();
+    let (size, from) = client.recv_from(&mut buf).unwrap();
+    assert_eq!(from, alternate_addr);
+    let response = Packet::from_bytes(&buf[..size]).unwrap();
+    assert_eq!(response.attributes()[1], Attribute::ResponseOrigin(alternate_addr));
+
+    stop.store(true, Ordering::SeqCst);
+    any_handler.join().unwrap();
+    primary_handler.join().unwrap();
+}
+
+#[test]
+fn retransmission_timeouts() {
+    let timeouts = client::retransmission_timeouts(Duration::from_millis(500), 7, 16).unwrap();
+    let mut elapsed = 0;
+    let mut sent_at = Vec::new();
+    for timeout in timeouts {
+        sent_at.push(elapsed);
+        elapsed += timeout.as_millis();
+    }
+    assert_eq!(sent_at, vec![0, 500, 1500, 3500, 7500, 15500, 31500]);
+    assert_eq!(elapsed, 39500);
+
+    // the timers saturate instead of overflowing, a request is sent at least once
+    let timeouts = client::retransmission_timeouts(Duration::from_secs(u64::MAX / 4), 40, 16).unwrap();
+    assert_eq!(timeouts[0], Duration::from_secs(u64::MAX / 4));
+    assert_eq!(timeouts[39], Duration::MAX);
+    assert!(matches!(client::retransmission_timeouts(Duration::from_millis(500), 0, 16),
+                     Err(Error::InvalidRetransmissionCount(0))));
+    let mut client = client::Client::new(Some("127.0.0.1")).unwrap();
+    assert!(matches!(client.set_retransmission(Duration::from_millis(500), 0, 16),
+                     Err(Error::InvalidRetransmissionCount(0))));
+}
+
+/// A client talking to a UDP socket on the loopback interface, the server side
+/// answers with `respond` every request after the first `drop` ones.
+fn binding_transaction<F>(dropped: usize, rc: u32, respond: F) -> (Result<SocketAddr, Error>, SocketAddr, usize)
+    where F: Fn(&[u8], &mut [u8], &SocketAddr, &SocketAddr) -> usize + Send + 'static {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let server_addr = socket.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut buf = [0u8; 2048];
+        let mut response = [0u8; 2048];
+        let mut requests = 0;
+        while let Ok((size, peer)) = socket.recv_from(&mut buf) {
+            requests += 1;
+            if requests > dropped {
+                let size = respond(&buf[..size], &mut response, &peer, &server_addr);
+                socket.send_to(&response[..size], peer).unwrap();
+            }
+        }
+        requests
+    });
+
+    let mut client = client::Client::new(Some("127.0.0.1:0")).unwrap();
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), rc, 2).unwrap();
+    let result = client.binding();
+    let local_addr = client.local_addr().unwrap();
+    (result, local_addr, server.join().unwrap())
+}
+
+#[test]
+fn client_binding() {
+    // The first request is lost, a stray response with another transaction id
+    // comes before the real one.
+    let (result, local_addr, requests) = binding_transaction(1, 7, |request, response, peer, local| {
+        let mut stray = [0u8; 2048];
+        let size = MessageBuilder::new(&mut stray, Class::SuccessResponse, Method::Binding, TransactionId::new([0; 12])).unwrap()
+            .attribute(&Attribute::XorMappedAddress("10.0.0.1:1".parse().unwrap())).unwrap()
+            .finish().unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(&stray[..size], peer).unwrap();
+        server::handler(request, response, peer, local).unwrap()
+    });
+    assert_eq!(result.unwrap(), local_addr);
+    assert_eq!(requests, 2);
+
+    let (result, _, _) = binding_transaction(0, 7, |request, response, _, _| {
+        let request = Packet::from_bytes(request).unwrap();
+        MessageBuilder::response(response, request.header(), Class::FailureResponse).unwrap()
+            .attribute(&Attribute::ErrorCode(ErrorCode::ServerError, "Try later".to_owned())).unwrap()
+            .finish().unwrap()
+    });
+    match result {
+        Err(Error::ErrorResponse { code: ErrorCode::ServerError, reason }) => assert_eq!(reason, "Try later"),
+        other => panic!("unexpected {:?}", other)
+    }
+
+    let (result, _, requests) = binding_transaction(usize::MAX, 3, |_, _, _, _| 0);
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert_eq!(requests, 3);
+}
+
+#[test]
+fn nat_detection() {
+    let server: SocketAddr  = "192.0.2.1:3478".parse().unwrap();
+    let changed: SocketAddr = "192.0.2.2:3479".parse().unwrap();
+    let local: SocketAddr   = "10.0.0.2:5000".parse().unwrap();
+    let mapped: SocketAddr  = "198.51.100.1:6000".parse().unwrap();
+
+    // (type, external address, answers change ip, answers change port only, mapping depends on the destination)
+    let cases = [
+        (client::Nat::OpenInternet,          local,  true,  true,  false),
+        (client::Nat::SymmetricFirewall,     local,  false, false, false),
+        (client::Nat::FullCone,              mapped, true,  true,  false),
+        (client::Nat::AddressRestrictedCone, mapped, false, true,  false),
+        (client::Nat::PortRestrictedCone,    mapped, false, false, false),
+        (client::Nat::Symmetric,             mapped, false, false, true)];
+    for &(nat, external, change_ip, change_port, symmetric) in cases.iter() {
+        let detected = client::detect_nat(server, local, |test| {
+            if (test.change_ip && !change_ip) || (test.change_port && !change_port) {
+                return Ok(None);
+            }
+            let mut mapped_address = external;
+            if symmetric && test.server != server {
+                mapped_address.set_port(external.port() + 1);
+            }
+            Ok(Some(client::NatResponse { mapped_address, changed_address: Some(changed), response_origin: None }))
+        });
+        assert_eq!(detected.unwrap(), nat);
+    }
+    assert_eq!(client::detect_nat(server, local, |_| Ok(None)).unwrap(), client::Nat::UdpBlocked);
+
+    let missing = client::detect_nat(server, local, |test| Ok(match test.change_ip {
+        true  => None,
+        false => Some(client::NatResponse { mapped_address: mapped, changed_address: None, response_origin: None })
+    }));
+    assert!(matches!(missing, Err(Error::MissingAttribute(0x0005))));
+
+    // Over the loopback interface the mapped address is the local one, the
+    // server has no other address and rejects CHANGE-REQUEST with a 420.
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let server_addr = socket.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut buf = [0u8; 2048];
+        let mut response = [0u8; 2048];
+        let mut classic = true;
+        while let Ok((size, peer)) = socket.recv_from(&mut buf) {
+            classic &= Header::from_bytes(&buf[..size]).unwrap().is_classic();
+            let reply = server::handle(&buf[..size], &mut response, &peer, &server_addr, None).unwrap();
+            socket.send_to(&response[..reply.size], reply.destination).unwrap();
+        }
+        classic
+    });
+    let mut client = client::Client::new(Some("127.0.0.1:0")).unwrap();
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), 2, 2).unwrap();
+    match client.nat() {
+        Err(Error::ErrorResponse { code: ErrorCode::UnknownAttribute, .. }) => { },
+        other => panic!("unexpected {:?}", other)
+    }
+    assert!(server.join().unwrap());
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_server_uri(&socket.local_addr().unwrap().to_string()).unwrap();
+    assert_eq!(client.nat().unwrap(), client::Nat::UdpBlocked);
+}
+
+#[test]
+fn nat_behavior_discovery() {
+    use client::Behavior::*;
+
+    let server: SocketAddr = "192.0.2.1:3478".parse().unwrap();
+    let other: SocketAddr  = "192.0.2.2:3479".parse().unwrap();
+    let local: SocketAddr  = "10.0.0.2:5000".parse().unwrap();
+    let mapped: SocketAddr = "198.51.100.1:6000".parse().unwrap();
+    let response = |mapped_address: SocketAddr, response_origin: SocketAddr| Some(client::NatResponse {
+        mapped_address,
+        changed_address: Some(other),
+        response_origin: Some(response_origin)
+    });
+    let first = response(mapped, server).unwrap();
+
+    for &(mapping, filtering) in [(EndpointIndependent, AddressDependent), (AddressDependent, AddressAndPortDependent),
+        (AddressAndPortDependent, EndpointIndependent)].iter() {
+        // A NAT allocating a new port for every destination address (and port).
+        let detected = client::mapping_behavior(server, local, &first, |test| {
+            let mut mapped_address = mapped;
+            if mapping != EndpointIndependent && test.server.ip() != server.ip() {
+                mapped_address.set_port(mapped.port() + 1);
+            }
+            if mapping == AddressAndPortDependent && test.server.port() != server.port() {
+                mapped_address.set_port(mapped.port() + 2);
+            }
+            Ok(response(mapped_address, test.server))
+        });
+        assert_eq!(detected.unwrap(), mapping);
+
+        let detected = client::filtering_behavior(server, &first, |test| {
+            let origin = SocketAddr::new(if test.change_ip { other.ip() } else { server.ip() },
+                if test.change_port { other.port() } else { server.port() });
+            Ok(match filtering {
+                EndpointIndependent     => response(mapped, origin),
+                AddressDependent        => if test.change_ip { None } else { response(mapped, origin) },
+                AddressAndPortDependent => None
+            })
+        });
+        assert_eq!(detected.unwrap(), filtering);
+    }
+    assert_eq!(client::mapping_behavior(server, mapped, &first, |_| Ok(None)).unwrap(), EndpointIndependent);
+    // A server ignoring CHANGE-REQUEST.
+    let ignored = client::filtering_behavior(server, &first, |_| Ok(response(mapped, server)));
+    assert!(matches!(ignored, Err(Error::UnexpectedResponseOrigin(origin)) if origin == server));
+    let first = client::NatResponse { changed_address: None, ..first };
+    assert!(matches!(client::filtering_behavior(server, &first, |_| Ok(None)), Err(Error::MissingAttribute(0x802C))));
+}
+
+/// A RFC 5780 server listening on two ports of 127.0.0.1, and on the same
+/// ports of `alternate_ip` when given. Without `alternate_ip` the alternate
+/// address only differs in port, which is all loopback tests need.
+/// Its handlers are stopped and joined when it is dropped.
+struct Rfc5780Server {
+    address: SocketAddr,
+    stop   : Arc<AtomicBool>,
+    handles: Vec<thread::JoinHandle<()>>
+}
+
+impl Drop for Rfc5780Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            handle.join().unwrap();
+        }
+    }
+}
+
+fn rfc5780_server(alternate_ip: Option<IpAddr>) -> Rfc5780Server {
+    let primary_ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let mut sockets = HashMap::new();
+    let mut ports = Vec::new();
+    for _ in 0..2 {
+        let socket = UdpSocket::bind((primary_ip, 0)).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        sockets.insert(socket.local_addr().unwrap(), socket);
+        if let Some(ip) = alternate_ip {
+            let socket = UdpSocket::bind((ip, port)).unwrap();
+            sockets.insert(socket.local_addr().unwrap(), socket);
+        }
+        ports.push(port);
+    }
+    // The handlers check the stop flag every time their read times out.
+    for socket in sockets.values() {
+        socket.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let handles = sockets.keys().map(|&local| {
+        let ip = match alternate_ip {
+            Some(ip) if local.ip() == primary_ip => ip,
+            _ => primary_ip
+        };
+        let alternate = SocketAddr::new(ip, if local.port() == ports[0] { ports[1] } else { ports[0] });
+        let senders: HashMap<SocketAddr, UdpSocket> = sockets.iter().map(|(addr, s)| (*addr, s.try_clone().unwrap())).collect();
+        let stop = stop.clone();
+        thread::spawn(move || server::udp_handler(&senders, &local, Some(&alternate), &stop))
+    }).collect();
+    Rfc5780Server { address: SocketAddr::new(primary_ip, ports[0]), stop, handles }
+}
+
+#[test]
+fn rfc5780_server_and_client() {
+    let server = rfc5780_server(None);
+    let server_addr = server.address;
+    let mut client = client::Client::new(Some("127.0.0.1:0")).unwrap();
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), 3, 2).unwrap();
+
+    let behavior = client.behavior().unwrap();
+    assert_eq!(behavior.mapped_address, client.local_addr().unwrap());
+    assert_eq!(behavior.mapping, client::Behavior::EndpointIndependent);
+    assert_eq!(behavior.filtering, client::Behavior::EndpointIndependent);
+    assert!(behavior.hairpinning);
+    // The hairpinning request is only waited for twice the RTO.
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let started = Instant::now();
+    assert!(!client.hairpinning(sink.local_addr().unwrap()).unwrap());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    // Over loopback the RFC 3489 tests see no NAT.
+    assert_eq!(client.nat().unwrap(), client::Nat::OpenInternet);
+
+    // RESPONSE-ORIGIN, OTHER-ADDRESS and RESPONSE-PORT.
+    let peer: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+    let local: SocketAddr = "127.0.0.1:3478".parse().unwrap();
+    let alternate: SocketAddr = "127.0.0.2:3479".parse().unwrap();
+    let mut request = [0u8; 64];
+    let size = MessageBuilder::new(&mut request, Class::Request, Method::Binding, TransactionId::new([1; 12])).unwrap()
+        .attribute(&Attribute::ChangeRequest { change_ip: false, change_port: true }).unwrap()
+        .attribute(&Attribute::ResponsePort(40001)).unwrap()
+        .finish().unwrap();
+    let mut response = [0u8; 128];
+    let reply = server::handle(&request[..size], &mut response, &peer, &local, Some(&alternate)).unwrap();
+    assert_eq!(reply.source, "127.0.0.1:3479".parse().unwrap());
+    assert_eq!(reply.destination, "127.0.0.1:40001".parse().unwrap());
+    let response = Packet::from_bytes(&response[..reply.size]).unwrap();
+    assert_eq!(response.attributes(), &vec![
+        Attribute::XorMappedAddress(peer),
+        Attribute::ResponseOrigin(reply.source),
+        Attribute::OtherAddress(alternate)]);
+}
+
+// Only some systems (Linux) route all of 127.0.0.0/8 to the loopback interface.
+#[cfg(target_os = "linux")]
+#[test]
+fn rfc5780_server_changed_ip() {
+    let alternate_ip: IpAddr = "127.0.0.2".parse().unwrap();
+    let server = rfc5780_server(Some(alternate_ip));
+    let server_addr = server.address;
+    let mut client = client::Client::new(Some("127.0.0.1:0")).unwrap();
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), 3, 2).unwrap();
+    let behavior = client.behavior().unwrap();
+    assert_eq!(behavior.mapping, client::Behavior::EndpointIndependent);
+    assert_eq!(behavior.filtering, client::Behavior::EndpointIndependent);
+    assert_eq!(client.nat().unwrap(), client::Nat::OpenInternet);
+
+    // CHANGE-REQUEST is answered from the alternate IP address.
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut request = [0u8; 64];
+    let size = MessageBuilder::new(&mut request, Class::Request, Method::Binding, TransactionId::new([1; 12])).unwrap()
+        .attribute(&Attribute::ChangeRequest { change_ip: true, change_port: false }).unwrap()
+        .finish().unwrap();
+    socket.send_to(&request[..size], server_addr).unwrap();
+    let mut buf = [0u8; 512];
+    let (size, from) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(from, SocketAddr::new(alternate_ip, server_addr.port()));
+    let response = Packet::from_bytes(&buf[..size]).unwrap();
+    assert_eq!(response.attributes()[1], Attribute::ResponseOrigin(from));
+    match response.attributes()[2] {
+        Attribute::OtherAddress(other) => assert_eq!(other.ip(), alternate_ip),
+        ref attribute => panic!("unexpected attribute {:?}", attribute)
+    }
+}
+
+/// A clock whose time only moves when it sleeps, `early` ones wake up
+/// halfway through their sleeps.
+struct SimulatedClock {
+    start  : Instant,
+    elapsed: Cell<Duration>,
+    early  : bool
+}
+
+impl client::Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+    fn sleep(&self, duration: Duration) {
+        let duration = if self.early { duration / 2 + Duration::from_nanos(1) } else { duration };
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+#[test]
+fn binding_lifetime() {
+    let max = Duration::from_secs(600);
+    let resolution = Duration::from_secs(1);
+
+    // A NAT dropping its bindings after 97 idle seconds, measured with a
+    // clock whose sleeps last as long as asked and with one waking up early.
+    let lifetime = Duration::from_secs(97);
+    for &early in &[false, true] {
+        let clock = SimulatedClock { start: Instant::now(), elapsed: Cell::new(Duration::from_secs(0)), early };
+        let trials = Cell::new(0);
+        let measured = client::binding_lifetime(&clock, max, resolution, || {
+            trials.set(trials.get() + 1);
+            Ok(client::Clock::now(&clock))
+        }, |opened| Ok(client::Clock::now(&clock) - *opened < lifetime)).unwrap();
+        let expired = measured.expired.unwrap();
+        assert!(measured.alive < lifetime && lifetime <= expired);
+        assert!(expired - measured.alive <= resolution);
+        assert!(trials.get() < 20);
+    }
+
+    let clock = SimulatedClock { start: Instant::now(), elapsed: Cell::new(Duration::from_secs(0)), early: false };
+
+    let measured = client::binding_lifetime(&clock, max, resolution, || Ok(()), |_| Ok(true)).unwrap();
+    assert_eq!(measured, client::BindingLifetime { alive: max, expired: None });
+    let measured = client::binding_lifetime(&clock, max, resolution, || Ok(()), |_| Ok(false)).unwrap();
+    assert_eq!(measured, client::BindingLifetime { alive: Duration::from_secs(0), expired: Some(resolution) });
+
+    // A wait the clock can't represent is rejected, long ones don't overflow.
+    assert!(matches!(client::binding_lifetime(&clock, Duration::MAX, resolution, || Ok(()), |_| Ok(true)),
+                     Err(Error::DurationTooLong(Duration::MAX))));
+    let long = Duration::from_secs(u64::MAX / 16);
+    let measured = client::binding_lifetime(&clock, long, resolution, || Ok(()), |_| Ok(true)).unwrap();
+    assert_eq!(measured, client::BindingLifetime { alive: long, expired: None });
+
+    // Without a NAT in between the binding never expires, RESPONSE-PORT
+    // sends the check response to the socket that opened the binding.
+    let server = rfc5780_server(None);
+    let server_addr = server.address;
+    let mut client = client::Client::new(Some("127.0.0.1:0")).unwrap();
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), 3, 2).unwrap();
+    let max = Duration::from_millis(40);
+    let measured = client.binding_lifetime(&client::SystemClock, max, Duration::from_millis(20)).unwrap();
+    assert_eq!(measured, client::BindingLifetime { alive: max, expired: None });
+
+    // A server answering the first request only: the binding looks expired,
+    // and its check is only waited for twice the RTO.
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_addr = socket.local_addr().unwrap();
+    let opener = thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut response = [0u8; 512];
+        let (size, peer) = socket.recv_from(&mut buf).unwrap();
+        let reply = server::handle(&buf[..size], &mut response, &peer, &server_addr, None).unwrap();
+        socket.send_to(&response[..reply.size], reply.destination).unwrap();
+    });
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(100), 7, 16).unwrap();
+    let started = Instant::now();
+    let measured = client.binding_lifetime(&client::SystemClock, max, max).unwrap();
+    assert_eq!(measured, client::BindingLifetime { alive: Duration::from_secs(0), expired: Some(max) });
+    assert!(started.elapsed() < Duration::from_secs(1));
+    opener.join().unwrap();
+}
+
+#[test]
+fn client_local_address() {
+    let client = client::Client::new(None).unwrap();
+    let local_addr = client.local_addr().unwrap();
+    assert!(local_addr.ip().is_unspecified() && local_addr.is_ipv4());
+    assert_ne!(local_addr.port(), 0);
+
+    let client = client::Client::new(Some("::")).unwrap();
+    let local_addr = client.local_addr().unwrap();
+    assert!(local_addr.ip().is_unspecified() && local_addr.is_ipv6());
+    assert_ne!(local_addr.port(), 0);
+    assert!(client::Client::new(Some("[::]:0")).unwrap().local_addr().unwrap().is_ipv6());
+
+    // Without a port every form binds an ephemeral one, not the STUN port.
+    for uri in &["localhost", "stun:127.0.0.1", "stun:0.0.0.0", "[::]", "stun:[::]", "stun://[::1]"] {
+        let local_addr = client::Client::new(Some(uri)).unwrap().local_addr().unwrap();
+        assert_ne!(local_addr.port(), 3478, "{}", uri);
+    }
+    let port = UdpSocket::bind("[::1]:0").unwrap().local_addr().unwrap().port();
+    assert_eq!(client::Client::new(Some(&format!("[::1]:{}", port))).unwrap().local_addr().unwrap(),
+               SocketAddr::new("::1".parse().unwrap(), port));
+    assert_eq!(ice::stun::url_parse_with_default_port("stun:[::1]", 0).unwrap(), "[::1]:0".parse().unwrap());
+    assert_eq!(ice::stun::url_parse("stun:[::1]").unwrap(), "[::1]:3478".parse().unwrap());
+
+    assert!(client::Client::new(Some("stun://127.0.0.1:99999")).is_err());
+    // Not an address of this host.
+    assert!(matches!(client::Client::new(Some("192.0.2.1:0")), Err(Error::Io(_))));
+    let mut client = client::Client::new(Some("0.0.0.0")).unwrap();
+    assert!(client.set_server_uri("stun://127.0.0.1:99999").is_err());
+    assert!(matches!(client.binding(), Err(Error::ServerNotSet)));
+
+    // Bound to any address, the client finds out the address it sends from.
+    let server = rfc5780_server(None);
+    let server_addr = server.address;
+    client.set_server_uri(&server_addr.to_string()).unwrap();
+    client.set_retransmission(Duration::from_millis(20), 3, 2).unwrap();
+    let mapped_address = client.binding().unwrap();
+    assert_eq!(mapped_address, SocketAddr::new(server_addr.ip(), client.local_addr().unwrap().port()));
+    assert_eq!(client.nat().unwrap(), client::Nat::OpenInternet);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_json_round_trip() {
+    for bytes in [&RFC5769_REQUEST[..], &RFC5769_IPV4_RESPONSE[..], &RFC5769_IPV6_RESPONSE[..], &RFC5769_LONG_TERM_REQUEST[..]] {
+        let packet = Packet::from_bytes(bytes).unwrap();
+        let json = serde_json::to_string(&packet).unwrap();
+        let decoded: Packet = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(decoded.into_bytes().unwrap(), bytes.to_vec());
+    }
+
+    let packet = Packet::from_bytes(&RFC5769_IPV4_RESPONSE).unwrap();
+    let json: serde_json::Value = serde_json::to_value(&packet).unwrap();
+    assert_eq!(json["header"]["class"], "SuccessResponse");
+    assert_eq!(json["header"]["method"], "Binding");
+    assert_eq!(json["header"]["transaction_id"], "b7e7a701bc34d686fa87dfae");
+    assert_eq!(json["attributes"][1]["XorMappedAddress"], "192.0.2.1:32853");
+    assert!(json["attributes"][2]["MessageIntegrity"].as_str().unwrap().starts_with("2b91f599"));
+
+    let attribute = Attribute::ErrorCode(ErrorCode::Other(499, "Odd".to_owned()), "Odd".to_owned());
+    let json = serde_json::to_string(&attribute).unwrap();
+    assert_eq!(json, r#"{"ErrorCode":{"code":499,"reason":"Odd"}}"#);
+    assert_eq!(serde_json::from_str::<Attribute>(&json).unwrap(), attribute);
+    assert_eq!(serde_json::to_string(&ErrorCode::StaleNonce).unwrap(), r#"{"code":438,"reason":"Stale nonce"}"#);
+    assert!(serde_json::from_str::<ErrorCode>(r#"{"code":700,"reason":""}"#).is_err());
+    assert!(serde_json::from_str::<TransactionId>(r#""b7e7a701""#).is_err());
+    assert!(serde_json::from_str::<Attribute>(r#"{"MessageIntegrity":"zz"}"#).is_err());
+}